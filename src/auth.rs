@@ -1,9 +1,241 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::signature::Verifier;
+use serde::Deserialize;
+use sha2::Sha256;
 use crate::messages::Auth;
 use crate::server::User;
+use crate::entitlements::Plan;
+use crate::config::Config;
+use crate::clock;
+use crate::normalize;
+use crate::jwks;
+use crate::client::GUEST_USERNAME;
 
-pub fn auth(auth: Auth) -> Option<User> {
-    // todo: actually perform authentication
+/// Tolerated clock difference to the token issuer in seconds.
+const LEEWAY: u64 = 60;
+/// Usernames of guests, which no token can claim. Guests are told apart by
+/// their username only, so it has to be unique.
+const GUEST_PREFIX: &str = "guest-";
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    exp: u64,
+    nbf: Option<u64>,
+    preferred_username: Option<String>,
+    plan: Option<String>,
+    #[serde(flatten)]
+    other: HashMap<String, serde_json::Value>,
+}
+
+/// Verifies the JWT with the configured HS256 secret, RS256 public key or
+/// keys of the JWKS url. Without any key configured tokens are refused,
+/// unless insecure development auth takes the token as the username.
+/// Empty tokens make the client a guest when guest access is enabled.
+pub fn auth(auth: Auth, config: &Config) -> Option<User> {
+    if auth.jwt_token.is_empty() && config.guest_access {
+        let username = format!("{}{:016x}", GUEST_PREFIX, rand::random::<u64>());
+        return Some(User {
+            display_name: username.clone(),
+            username,
+            user_id: None,
+            plan: Plan::Free,
+            guest: true,
+            claims: HashMap::new(),
+            expires_at: None,
+            admin: false,
+        });
+    }
+
+    if !config.has_token_key() {
+        let username = normalize::username(auth.jwt_token);
+        if !config.insecure_dev_auth || is_reserved(&username) {
+            return None;
+        }
+        return Some(User {
+            display_name: username.clone(),
+            username,
+            user_id: None,
+            plan: Plan::Free,
+            guest: false,
+            claims: HashMap::new(),
+            expires_at: None,
+            admin: false,
+        });
+    }
+
+    let claims = verify(auth.jwt_token, config)?;
+    let now = clock::now();
+    if claims.exp + LEEWAY < now || claims.nbf.map(|x| x > now + LEEWAY).unwrap_or(false) {
+        return None;
+    }
+
+    /* the preferred username can be changed by the user, only the subject identifies them */
+    let display_name = normalize::username(claims.preferred_username.as_ref().unwrap_or(&claims.sub));
+    if is_reserved(&claims.sub) || is_reserved(&display_name) {
+        return None;
+    }
+
+    let plan = Plan::from_name(claims.plan.as_ref().map(|x| x.as_str()));
+    let admin = config.admins.contains(&claims.sub);
     Some(User {
-        username: auth.jwt_token.to_string()
+        username: claims.sub.clone(),
+        display_name,
+        user_id: Some(claims.sub),
+        plan,
+        guest: false,
+        claims: claims.other.into_iter().filter_map(|(k, v)| v.as_str().map(|v| (k, v.to_string()))).collect(),
+        expires_at: Some(claims.exp),
+        admin,
     })
 }
+
+/// Usernames of guests and of spectators joining by a view token.
+fn is_reserved(username: &str) -> bool {
+    username.starts_with(GUEST_PREFIX) || username == GUEST_USERNAME
+}
+
+/// Whether the token of the user lapsed, with the same leeway as when it
+/// was verified.
+pub fn is_expired(user: &User) -> bool {
+    user.expires_at.map(|x| x + LEEWAY < clock::now()).unwrap_or(false)
+}
+
+/// Returns claims of the token when its signature is valid. The algorithm
+/// must match the configured key so an RS256 public key can never be used
+/// as an HS256 secret.
+fn verify(token: &str, config: &Config) -> Option<Claims> {
+    let mut parts = token.split('.');
+    let (header, payload, signature) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature), None) => (header, payload, signature),
+        _ => return None,
+    };
+    let signed = &token[..header.len() + 1 + payload.len()];
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    let header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    /* tokens of the identity provider name the key, rotated keys are fetched on demand */
+    let jwks_key = match (&config.jwks_url, &header.kid) {
+        (Some(_), Some(kid)) => jwks::key(kid),
+        _ => None,
+    };
+    match (header.alg.as_str(), &config.jwt_secret, jwks_key.as_ref().or(config.jwt_public_key.as_ref())) {
+        ("HS256", Some(secret), _) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+            mac.update(signed.as_bytes());
+            mac.verify_slice(&signature).ok()?;
+        }
+        ("RS256", _, Some(key)) => {
+            let signature = Signature::try_from(signature.as_slice()).ok()?;
+            VerifyingKey::<Sha256>::new(key.clone()).verify(signed.as_bytes(), &signature).ok()?;
+        }
+        _ => return None,
+    }
+
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use crate::config::Config;
+    use crate::messages::Auth;
+    use crate::clock;
+    use super::{auth, is_expired};
+
+    fn hs256(claims: &str, secret: &[u8]) -> String {
+        let signed = format!("{}.{}", URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#), URL_SAFE_NO_PAD.encode(claims));
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_hs256() {
+        let mut config = Config::from_env();
+        config.jwt_secret = Some(b"secret".to_vec());
+        config.jwt_public_key = None;
+        config.jwks_url = None;
+
+        let valid = format!(r#"{{"sub":"42","preferred_username":"alice","exp":{}}}"#, clock::now() + 600);
+        let user = auth(Auth { jwt_token: &hs256(&valid, b"secret") }, &config).unwrap();
+        assert_eq!(user.username, "42");
+        assert_eq!(user.display_name, "alice");
+        assert_eq!(user.user_id.as_ref().map(|x| x.as_str()), Some("42"));
+        assert!(!is_expired(&user));
+        assert!(!user.admin);
+
+        config.admins = vec!["42".to_string()];
+        assert!(auth(Auth { jwt_token: &hs256(&valid, b"secret") }, &config).unwrap().admin);
+
+        assert!(auth(Auth { jwt_token: &hs256(&valid, b"other") }, &config).is_none());
+        let expired = format!(r#"{{"sub":"42","exp":{}}}"#, clock::now() - 600);
+        assert!(auth(Auth { jwt_token: &hs256(&expired, b"secret") }, &config).is_none());
+        assert!(auth(Auth { jwt_token: "alice" }, &config).is_none());
+
+        let guest = format!(r#"{{"sub":"43","preferred_username":"guest-0042","exp":{}}}"#, clock::now() + 600);
+        assert!(auth(Auth { jwt_token: &hs256(&guest, b"secret") }, &config).is_none());
+    }
+
+    #[test]
+    fn test_same_preferred_username() {
+        let mut config = Config::from_env();
+        config.jwt_secret = Some(b"secret".to_vec());
+        config.jwt_public_key = None;
+        config.jwks_url = None;
+
+        let alice = format!(r#"{{"sub":"42","preferred_username":"alice","exp":{}}}"#, clock::now() + 600);
+        let impostor = format!(r#"{{"sub":"43","preferred_username":"alice","exp":{}}}"#, clock::now() + 600);
+        let alice = auth(Auth { jwt_token: &hs256(&alice, b"secret") }, &config).unwrap();
+        let impostor = auth(Auth { jwt_token: &hs256(&impostor, b"secret") }, &config).unwrap();
+        assert_eq!(alice.display_name, impostor.display_name);
+        assert_ne!(alice.username, impostor.username);
+
+        let guest = format!(r#"{{"sub":"guest-0042","preferred_username":"bob","exp":{}}}"#, clock::now() + 600);
+        assert!(auth(Auth { jwt_token: &hs256(&guest, b"secret") }, &config).is_none());
+    }
+
+    #[test]
+    fn test_guest() {
+        let mut config = Config::from_env();
+        config.jwt_secret = Some(b"secret".to_vec());
+
+        config.guest_access = false;
+        assert!(auth(Auth { jwt_token: "" }, &config).is_none());
+
+        config.guest_access = true;
+        let user = auth(Auth { jwt_token: "" }, &config).unwrap();
+        assert!(user.guest && user.username.starts_with("guest-"));
+        assert_ne!(auth(Auth { jwt_token: "" }, &config).unwrap().username, user.username);
+        assert!(auth(Auth { jwt_token: "alice" }, &config).is_none());
+    }
+
+    #[test]
+    fn test_without_key() {
+        let mut config = Config::from_env();
+        config.jwt_secret = None;
+        config.jwt_public_key = None;
+        config.jwks_url = None;
+
+        config.insecure_dev_auth = false;
+        assert!(auth(Auth { jwt_token: "alice" }, &config).is_none());
+
+        config.insecure_dev_auth = true;
+        assert_eq!(auth(Auth { jwt_token: "alice" }, &config).unwrap().username, "alice");
+        assert!(auth(Auth { jwt_token: "guest-0042" }, &config).is_none());
+    }
+}
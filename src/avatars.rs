@@ -0,0 +1,114 @@
+//! Avatars are fetched by the server and served from `/avatars/{user_id}`
+//! so clients neither reveal their address to avatar hosts nor wait for
+//! them when rendering presence.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::profiles::urlencode;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Avatar {
+    pub content_type: &'static str,
+    pub data: Arc<Vec<u8>>,
+}
+
+/// Avatars by user id. Failed fetches are cached as well so a broken
+/// avatar host is not asked on every join.
+pub struct AvatarCache {
+    max_bytes: usize,
+    pub ttl: Duration,
+    entries: Mutex<HashMap<String, (Option<Avatar>, Instant)>>,
+}
+
+impl AvatarCache {
+    pub fn new(max_bytes: usize, ttl: Duration) -> Self {
+        AvatarCache {
+            max_bytes,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_cached(&self, user_id: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, at)| at.elapsed() < self.ttl);
+        entries.contains_key(user_id)
+    }
+
+    pub fn get(&self, user_id: &str) -> Option<(&'static str, Arc<Vec<u8>>)> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(user_id) {
+            Some((Some(avatar), at)) if at.elapsed() < self.ttl => Some((avatar.content_type, avatar.data.clone())),
+            _ => None,
+        }
+    }
+
+    /// Proxied url of the avatar, empty when it could not be fetched so
+    /// clients never load it from the original host.
+    pub fn url(&self, user_id: &str) -> String {
+        match self.get(user_id) {
+            Some(_) => format!("/avatars/{}", urlencode(user_id)),
+            None => String::new(),
+        }
+    }
+
+    /// Downloads the avatar, blocking until it arrives.
+    pub fn fetch(&self, user_id: &str, url: &str) -> Result<(), String> {
+        let result = download(url, self.max_bytes);
+        let avatar = result.as_ref().ok().map(|(content_type, data)| Avatar { content_type, data: Arc::new(data.clone()) });
+        self.entries.lock().unwrap().insert(user_id.to_string(), (avatar, Instant::now()));
+        result.map(|_| ())
+    }
+}
+
+fn download(url: &str, max_bytes: usize) -> Result<(&'static str, Vec<u8>), String> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("{} is not an http url", url));
+    }
+
+    let response = ureq::get(url).timeout(FETCH_TIMEOUT).call().map_err(|e| e.to_string())?;
+    let declared = response.content_type().to_string();
+
+    let mut data = vec![];
+    response.into_reader().take(max_bytes as u64 + 1).read_to_end(&mut data).map_err(|e| e.to_string())?;
+    if data.len() > max_bytes {
+        return Err(format!("avatar is larger than {} bytes", max_bytes));
+    }
+
+    match sniff(&data) {
+        Some(t) if t == declared => Ok((t, data)),
+        _ => Err(format!("{} is not a supported image", declared)),
+    }
+}
+
+/// Type of the image by its signature. SVG is not supported as it may
+/// contain scripts.
+pub fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::sniff;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(sniff(b"RIFF"), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Reconstructs a board from its history exported as JSON Lines by
+//! creating the board on a running server and sending every stored
+//! mutation again. Messages only the server sends are skipped.
+//!
+//! Identical mutations sent quickly after each other are dropped as
+//! duplicates, disable the `duplicate_detection` feature for the board
+//! owner while importing histories containing them.
+//!
+//! Usage: `history-import <history.jsonl> <url> <auth token> <board name>`
+
+use std::env;
+use std::fs;
+use std::thread;
+use ws::{connect, CloseCode};
+
+#[allow(dead_code)]
+#[path = "../error.rs"]
+mod error;
+#[allow(dead_code)]
+#[path = "../ser.rs"]
+mod ser;
+#[allow(dead_code)]
+#[path = "../de.rs"]
+mod de;
+#[allow(dead_code)]
+#[path = "../messages.rs"]
+mod messages;
+
+use messages::{Message, Auth, Create};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 5 {
+        eprintln!("usage: {} <history.jsonl> <url> <auth token> <board name>", args[0]);
+        std::process::exit(2);
+    }
+
+    let jsonl = fs::read_to_string(&args[1]).expect("cannot read history");
+    let mut frames = vec![
+        ser::to_bytes(&Message::Auth(Auth { jwt_token: &args[3] })).unwrap(),
+        ser::to_bytes(&Message::Create(Create { template_id: 0, name: &args[4] })).unwrap(),
+    ];
+    let mut skipped = 0;
+    for (i, line) in jsonl.lines().enumerate().filter(|(_, x)| !x.trim().is_empty()) {
+        let raw = serde_json::from_str::<serde_json::Value>(line).ok()
+            .and_then(|x| x["raw"].as_str().and_then(from_hex))
+            .unwrap_or_else(|| panic!("line {} is not a history entry", i + 1));
+        match de::from_bytes::<Message>(&raw) {
+            Ok(msg) if msg.is_mutation() => frames.push(raw),
+            Ok(_) => skipped += 1,
+            Err(e) => panic!("line {} holds an invalid message: {:?}", i + 1, e),
+        }
+    }
+
+    println!("Importing {} messages into {}, skipping {}", frames.len() - 2, args[4], skipped);
+    let mut frames = Some(frames);
+    connect(args[2].clone(), |out| {
+        let frames = frames.take().unwrap_or_default();
+        thread::spawn(move || {
+            for frame in frames {
+                if out.send(frame).is_err() {
+                    return;
+                }
+            }
+            let _ = out.close(CloseCode::Normal);
+        });
+        |_| Ok(())
+    }).expect("cannot connect");
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
@@ -0,0 +1,69 @@
+//! Replays a capture file recorded by the server against a running
+//! server. Every captured connection is opened again and its frames are
+//! sent with the original timing divided by the speed factor.
+//!
+//! Usage: `replay <capture> <url> [speed]`
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use ws::{connect, CloseCode};
+
+#[allow(dead_code)]
+#[path = "../capture.rs"]
+mod capture;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: {} <capture> <url> [speed]", args[0]);
+        std::process::exit(2);
+    }
+
+    let url = args[2].clone();
+    let speed: f64 = args.get(3).and_then(|x| x.parse().ok()).filter(|x| *x > 0.0).unwrap_or(1.0);
+    let records = capture::read(Path::new(&args[1])).expect("cannot read capture");
+    let first = match records.first() {
+        Some(t) => t.timestamp,
+        None => return,
+    };
+
+    let mut connections: BTreeMap<u32, Vec<capture::Record>> = BTreeMap::new();
+    for record in records {
+        connections.entry(record.connection).or_default().push(record);
+    }
+
+    println!("Replaying {} connections at {}x speed", connections.len(), speed);
+    let start = Instant::now();
+    let threads: Vec<_> = connections.into_iter().map(|(id, records)| {
+        let url = url.clone();
+        thread::spawn(move || {
+            let mut records = Some(records);
+            connect(url, |out| {
+                let records = records.take().unwrap_or_default();
+                thread::spawn(move || {
+                    for record in records {
+                        let offset = Duration::from_millis(((record.timestamp - first) as f64 / speed) as u64);
+                        if let Some(wait) = offset.checked_sub(start.elapsed()) {
+                            thread::sleep(wait);
+                        }
+                        if out.send(record.frame).is_err() {
+                            return;
+                        }
+                    }
+                    let _ = out.close(CloseCode::Normal);
+                });
+                /* ws dictates the error type of handlers */
+                #[allow(clippy::result_large_err)]
+                let handler = move |_| Ok(());
+                handler
+            }).unwrap_or_else(|e| eprintln!("connection {} failed: {}", id, e));
+        })
+    }).collect();
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+}
@@ -0,0 +1,323 @@
+use crate::messages::{Color, Position, Draw, Fill, Bounds, CurveStroke, Brush, BlendMode, PALETTE_SIZE};
+
+pub const CANVAS_WIDTH: u32 = 1920;
+pub const CANVAS_HEIGHT: u32 = 1080;
+pub const MINIMAP_SCALE: u32 = 24;
+pub const TILE_SIZE: u32 = 64;
+
+/// Brushes referenced by id in draw flags: pen, marker, soft brush and
+/// highlighter. Unknown ids draw with the pen.
+pub const BRUSHES: [Brush; 4] = [
+    Brush { radius: 0, alpha: 255, soft: false },
+    Brush { radius: 2, alpha: 255, soft: false },
+    Brush { radius: 3, alpha: 160, soft: true },
+    Brush { radius: 4, alpha: 96, soft: false },
+];
+const TILES_X: u32 = (CANVAS_WIDTH + TILE_SIZE - 1) / TILE_SIZE;
+const TILES_Y: u32 = (CANVAS_HEIGHT + TILE_SIZE - 1) / TILE_SIZE;
+
+/// Server side model of board pixels holding palette indices. Positions
+/// are encoded as `y * CANVAS_WIDTH + x`.
+///
+/// Every change increments the canvas version and stamps the touched
+/// tiles with it, so changes since any version can be found cheaply.
+#[derive(Clone)]
+pub struct Canvas {
+    pixels: Vec<Color>,
+    version: u32,
+    tile_versions: Vec<u32>,
+}
+
+impl Canvas {
+    pub fn new(background: Color) -> Self {
+        Canvas {
+            pixels: vec![background; (CANVAS_WIDTH * CANVAS_HEIGHT) as usize],
+            version: 0,
+            tile_versions: vec![0; (TILES_X * TILES_Y) as usize],
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Stamps the brush of the draw flags. Translucent pixels are blended
+    /// in RGB and stored as the nearest palette color, the same way
+    /// clients quantize their strokes.
+    pub fn draw(&mut self, t: &Draw, palette: &[u32]) {
+        if !contains(t.position) {
+            return;
+        }
+
+        let brush = BRUSHES.get(t.flags.brush() as usize).unwrap_or(&BRUSHES[0]);
+        let (x, y) = coords(t.position);
+        let radius = brush.radius as u32;
+        let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (x1, y1) = ((x + radius).min(CANVAS_WIDTH - 1), (y + radius).min(CANVAS_HEIGHT - 1));
+
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let distance = ((px as f64 - x as f64).powi(2) + (py as f64 - y as f64).powi(2)).sqrt();
+                if distance > radius as f64 {
+                    continue;
+                }
+
+                let alpha = match brush.soft {
+                    true => (brush.alpha as f64 * (1.0 - distance / (radius + 1) as f64)) as u8,
+                    false => brush.alpha,
+                };
+                let pixel = &mut self.pixels[(py * CANVAS_WIDTH + px) as usize];
+                *pixel = blend(palette, *pixel, t.color, alpha, t.flags.blend_mode());
+            }
+        }
+        self.touch(x0, y0, x1, y1);
+    }
+
+    pub fn fill(&mut self, t: &Fill) {
+        let (x0, y0) = coords(t.start);
+        let (x1, y1) = coords(t.end);
+        let (x0, x1) = (x0.min(x1), x0.max(x1).min(CANVAS_WIDTH - 1));
+        let (y0, y1) = (y0.min(y1), y0.max(y1).min(CANVAS_HEIGHT - 1));
+
+        if x0 > x1 || y0 > y1 {
+            return;
+        }
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                self.pixels[(y * CANVAS_WIDTH + x) as usize] = t.color;
+            }
+        }
+        self.touch(x0, y0, x1, y1);
+    }
+
+    /// Rasterizes the curve sampling each segment densely enough for
+    /// consecutive samples to be at most a pixel apart.
+    pub fn curve(&mut self, t: &CurveStroke) {
+        let (mut x0, mut y0, mut x1, mut y1) = (CANVAS_WIDTH, CANVAS_HEIGHT, 0, 0);
+        let mut from = point(t.start);
+        for segment in &t.segments {
+            let points = [from, point(segment.control1), point(segment.control2), point(segment.end)];
+            let length: f64 = points.windows(2).map(|x| ((x[1].0 - x[0].0).powi(2) + (x[1].1 - x[0].1).powi(2)).sqrt()).sum();
+            let steps = length.ceil().max(1.0) as u32;
+
+            for i in 0..=steps {
+                let s = i as f64 / steps as f64;
+                let r = 1.0 - s;
+                let weights = [r * r * r, 3.0 * r * r * s, 3.0 * r * s * s, s * s * s];
+                let x = (0..4).map(|i| weights[i] * points[i].0).sum::<f64>().round() as u32;
+                let y = (0..4).map(|i| weights[i] * points[i].1).sum::<f64>().round() as u32;
+                if x < CANVAS_WIDTH && y < CANVAS_HEIGHT {
+                    self.pixels[(y * CANVAS_WIDTH + x) as usize] = t.color;
+                    x0 = x0.min(x);
+                    y0 = y0.min(y);
+                    x1 = x1.max(x);
+                    y1 = y1.max(y);
+                }
+            }
+            from = points[3];
+        }
+
+        if x0 <= x1 && y0 <= y1 {
+            self.touch(x0, y0, x1, y1);
+        }
+    }
+
+    fn touch(&mut self, x0: u32, y0: u32, x1: u32, y1: u32) {
+        self.version = self.version.wrapping_add(1);
+        for ty in y0 / TILE_SIZE..=y1 / TILE_SIZE {
+            for tx in x0 / TILE_SIZE..=x1 / TILE_SIZE {
+                self.tile_versions[(ty * TILES_X + tx) as usize] = self.version;
+            }
+        }
+    }
+
+    /// Returns bounds of all tiles changed after the specified version.
+    pub fn changed_tiles(&self, since: u32) -> Vec<Bounds> {
+        let mut tiles = vec![];
+        for ty in 0..TILES_Y {
+            for tx in 0..TILES_X {
+                if self.tile_versions[(ty * TILES_X + tx) as usize] <= since {
+                    continue;
+                }
+
+                let (x0, y0) = (tx * TILE_SIZE, ty * TILE_SIZE);
+                let (x1, y1) = ((x0 + TILE_SIZE).min(CANVAS_WIDTH) - 1, (y0 + TILE_SIZE).min(CANVAS_HEIGHT) - 1);
+                tiles.push(Bounds {
+                    start: y0 * CANVAS_WIDTH + x0,
+                    end: y1 * CANVAS_WIDTH + x1,
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Copies pixels of the region split into row bands so that every band
+    /// fits into a single message.
+    pub fn region(&self, bounds: &Bounds) -> Vec<(Bounds, Vec<Color>)> {
+        let (x0, y0) = coords(bounds.start);
+        let (x1, y1) = coords(bounds.end);
+        let (x0, x1) = (x0.min(x1), x0.max(x1).min(CANVAS_WIDTH - 1));
+        let (y0, y1) = (y0.min(y1), y0.max(y1).min(CANVAS_HEIGHT - 1));
+
+        if x0 > x1 || y0 > y1 {
+            return vec![];
+        }
+
+        let rows_per_band = (((1 << 16) - 1) / (x1 - x0 + 1)).max(1);
+        let mut bands = vec![];
+        let mut y = y0;
+        while y <= y1 {
+            let band_end = (y + rows_per_band - 1).min(y1);
+            let mut data = Vec::with_capacity(((x1 - x0 + 1) * (band_end - y + 1)) as usize);
+            for row in y..=band_end {
+                let offset = (row * CANVAS_WIDTH) as usize;
+                data.extend_from_slice(&self.pixels[offset + x0 as usize..=offset + x1 as usize]);
+            }
+
+            bands.push((Bounds {
+                start: y * CANVAS_WIDTH + x0,
+                end: band_end * CANVAS_WIDTH + x1,
+            }, data));
+            y = band_end + 1;
+        }
+
+        bands
+    }
+
+    /// Renders the canvas as an 8-bit indexed BMP image. Colors outside
+    /// of the palette are rendered black.
+    pub fn to_bmp(&self, palette: &[u32]) -> Vec<u8> {
+        let offset = 14 + 40 + 256 * 4;
+        let size = offset + self.pixels.len() as u32;
+        let mut bmp = Vec::with_capacity(size as usize);
+
+        /* file header */
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&size.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&offset.to_le_bytes());
+
+        /* info header */
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&CANVAS_WIDTH.to_le_bytes());
+        bmp.extend_from_slice(&CANVAS_HEIGHT.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&8u16.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&(self.pixels.len() as u32).to_le_bytes());
+        bmp.extend_from_slice(&2835u32.to_le_bytes());
+        bmp.extend_from_slice(&2835u32.to_le_bytes());
+        bmp.extend_from_slice(&256u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+
+        /* color table, palette colors are stored as 0x00BBGGRR */
+        for i in 0..256 {
+            let color = palette.get(i).cloned().unwrap_or(0);
+            bmp.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, color as u8, 0]);
+        }
+
+        /* rows are stored bottom-up, width is a multiple of 4 so no padding */
+        for row in (0..CANVAS_HEIGHT).rev() {
+            let offset = (row * CANVAS_WIDTH) as usize;
+            bmp.extend_from_slice(&self.pixels[offset..offset + CANVAS_WIDTH as usize]);
+        }
+
+        bmp
+    }
+
+    /// Downscales the canvas so that each cell holds the most frequent
+    /// color of the `MINIMAP_SCALE`x`MINIMAP_SCALE` pixel block it covers.
+    pub fn minimap(&self) -> (u16, u16, Vec<Color>) {
+        let width = CANVAS_WIDTH / MINIMAP_SCALE;
+        let height = CANVAS_HEIGHT / MINIMAP_SCALE;
+        let mut cells = Vec::with_capacity((width * height) as usize);
+
+        for cy in 0..height {
+            for cx in 0..width {
+                let mut histogram = [0u16; 256];
+                for y in cy * MINIMAP_SCALE..(cy + 1) * MINIMAP_SCALE {
+                    for x in cx * MINIMAP_SCALE..(cx + 1) * MINIMAP_SCALE {
+                        histogram[self.pixels[(y * CANVAS_WIDTH + x) as usize] as usize] += 1;
+                    }
+                }
+
+                let (color, _) = histogram.iter().enumerate().max_by_key(|(_, count)| **count).unwrap();
+                cells.push(color as Color);
+            }
+        }
+
+        (width as u16, height as u16, cells)
+    }
+}
+
+/// Names the ninth of the canvas the position lies in, e.g. `top-left`.
+pub fn area(position: Position) -> &'static str {
+    let (x, y) = coords(position);
+    let column = (x * 3 / CANVAS_WIDTH).min(2);
+    let row = (y * 3 / CANVAS_HEIGHT).min(2);
+    match (row, column) {
+        (0, 0) => "top-left",
+        (0, 1) => "top",
+        (0, _) => "top-right",
+        (1, 0) => "left",
+        (1, 1) => "center",
+        (1, _) => "right",
+        (_, 0) => "bottom-left",
+        (_, 1) => "bottom",
+        (_, _) => "bottom-right",
+    }
+}
+
+/// Moves the position by the offset, `None` when it would leave the
+/// canvas.
+pub fn translate(position: Position, dx: i16, dy: i16) -> Option<Position> {
+    let (x, y) = coords(position);
+    let (x, y) = (x as i64 + dx as i64, y as i64 + dy as i64);
+    if x < 0 || y < 0 || x >= CANVAS_WIDTH as i64 || y >= CANVAS_HEIGHT as i64 {
+        return None;
+    }
+    Some(y as u32 * CANVAS_WIDTH + x as u32)
+}
+
+fn coords(position: Position) -> (u32, u32) {
+    (position % CANVAS_WIDTH, position / CANVAS_WIDTH)
+}
+
+/// Composes the color over the pixel with the alpha.
+fn blend(palette: &[u32], pixel: Color, color: Color, alpha: u8, mode: BlendMode) -> Color {
+    if alpha == 255 && mode == BlendMode::Alpha {
+        return color;
+    }
+
+    let rgb = |x: Color| palette.get(x as usize).cloned().unwrap_or(0);
+    let (under, over) = (rgb(pixel), rgb(color));
+    let channel = |shift: u32| {
+        let (a, b) = ((under >> shift & 0xff) as u32, (over >> shift & 0xff) as u32);
+        let b = match mode {
+            BlendMode::Alpha => b,
+            BlendMode::Multiply => a * b / 255,
+        };
+        ((a * (255 - alpha as u32) + b * alpha as u32) / 255) as u8
+    };
+    nearest(palette, (channel(0), channel(8), channel(16)))
+}
+
+/// Palette index of the color closest to the RGB value.
+pub fn nearest(palette: &[u32], (r, g, b): (u8, u8, u8)) -> Color {
+    let distance = |x: u32| {
+        let channel = |shift: u32, value: u8| ((x >> shift & 0xff) as i32 - value as i32).pow(2);
+        channel(0, r) + channel(8, g) + channel(16, b)
+    };
+    (0..PALETTE_SIZE.min(palette.len())).min_by_key(|i| distance(palette[*i])).unwrap_or(0) as Color
+}
+
+fn point(position: Position) -> (f64, f64) {
+    let (x, y) = coords(position);
+    (x as f64, y as f64)
+}
+
+/// Whether the position lies on the canvas.
+pub fn contains(position: Position) -> bool {
+    position < CANVAS_WIDTH * CANVAS_HEIGHT
+}
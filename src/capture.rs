@@ -0,0 +1,59 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Inbound frame of a capture file. Records are stored one after another
+/// as timestamp in milliseconds (u64), connection id (u32) and frame
+/// length (u32), all little-endian, followed by the frame itself.
+#[derive(Clone)]
+pub struct Record {
+    pub timestamp: u64,
+    pub connection: u32,
+    pub frame: Vec<u8>,
+}
+
+pub fn append(path: &Path, record: &Record) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(16 + record.frame.len());
+    bytes.extend_from_slice(&record.timestamp.to_le_bytes());
+    bytes.extend_from_slice(&record.connection.to_le_bytes());
+    bytes.extend_from_slice(&(record.frame.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&record.frame);
+
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(&bytes)
+}
+
+/// Reads all records of the capture, used by the replay tool.
+#[allow(dead_code)]
+pub fn read(path: &Path) -> io::Result<Vec<Record>> {
+    let bytes = fs::read(path)?;
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "truncated capture");
+
+    let mut records = vec![];
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < 16 {
+            return Err(truncated());
+        }
+
+        let mut timestamp = [0; 8];
+        let mut connection = [0; 4];
+        let mut length = [0; 4];
+        timestamp.copy_from_slice(&rest[0..8]);
+        connection.copy_from_slice(&rest[8..12]);
+        length.copy_from_slice(&rest[12..16]);
+
+        let end = 16 + u32::from_le_bytes(length) as usize;
+        if rest.len() < end {
+            return Err(truncated());
+        }
+
+        records.push(Record {
+            timestamp: u64::from_le_bytes(timestamp),
+            connection: u32::from_le_bytes(connection),
+            frame: rest[16..end].to_vec(),
+        });
+        rest = &rest[end..];
+    }
+
+    Ok(records)
+}
@@ -1,53 +1,242 @@
-use ws::{Sender, Error, Handler, CloseCode, Message, ErrorKind};
-use crate::messages::{Message as ObMessage, Create, Join};
-use crate::de::from_bytes;
+use ws::{Error, Handler, CloseCode, Message, ErrorKind, Request, Response, Handshake};
+use ws::util::Token;
+use crate::messages::{Message as ObMessage, Create, Join, CreatePoll, Vote, ClosePoll, PlaceVote, Stamp, SetGrid, SetBackground, Connector, CreateFrame, JumpToFrame, Draw, Fill, RequestRegion, Role, CreateInvite, Invite as InviteMessage, JoinInvite, SetPrivate, RequestJoin, RespondJoin, NotificationFlags, PresenceState, TimeSync, CreateViewToken, ViewToken as ViewTokenMessage, JoinView, Idempotent, AckWindow, Provisional, StepAssigned, ExportProgress, ExportReady, Reconnect, ClientHello, Reliability, Search, SearchResults, RequestUserViewport, Follow, Text, SetPalettePreset, CurveStroke, ClearRegion, GuestIdentity, Group, CopyObjects, PasteObjects, CreateFromTemplate, TemplateVariable, EnterPortal, ServerMessage, ReAuth, Auth, Resume, SetAccess, AdminBroadcast, AdminDeleteBoard, TimelineKind};
+use crate::de::{from_bytes, from_bytes_prefix};
 use crate::server::User;
-use crate::server::Server;
+use crate::entitlements::Plan;
+use crate::server::{Server, Board, send_history};
 use std::cell::RefCell;
-use crate::auth::auth;
-use log::{info, warn};
+use std::collections::HashMap;
+use crate::auth::{auth, is_expired};
+use crate::invite::{Invite, ViewToken};
+use crate::ser::to_bytes;
+use crate::clock;
+use crate::http;
+use crate::i18n::Locale;
+use crate::quarantine;
+use crate::exports;
+use crate::logging::TRACE_TARGET;
+use crate::features::{self, Feature};
+use crate::transport::Out;
+use crate::invite::to_hex;
+use crate::capture::{self, Record};
+use crate::request::{self, RequestId};
+use crate::outbox::{self, Outbox};
+use crate::housekeeping;
+use crate::normalize;
+use log::{info, warn, error};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Instant, Duration};
+
+/// Delay after joining before the full history is sent, giving the client
+/// time to request the region of its initial viewport.
+const HISTORY_BACKFILL_DELAY_MS: u64 = 250;
+const HISTORY_BACKFILL: Token = Token(1);
+const JOIN_RESPONSE: Token = Token(2);
+
+/// Clients without any activity for this long are marked as away.
+const IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+const IDLE_CHECK_INTERVAL_MS: u64 = 30 * 1000;
+pub const IDLE_CHECK: Token = Token(3);
+
+/// Interval of acknowledging processed frames to the client.
+const ACK_INTERVAL_MS: u64 = 1000;
+const ACK_WINDOW: Token = Token(4);
+
+/// Shows the profile of the user in the board, fired again by the job
+/// which resolved it when it was not cached.
+const PROFILE: Token = Token(6);
+
+/// Frames kept before joining a board, enough for auth and join.
+const MAX_PREAMBLE: usize = 8;
+
+/// Number of panics caught in message and timeout handlers since start.
+pub static HANDLER_PANICS: AtomicUsize = AtomicUsize::new(0);
+
+/// Mutations in a single group.
+const MAX_GROUP_MESSAGES: usize = 256;
+
+/// Username of connections entering a board with a view token only.
+pub const GUEST_USERNAME: &str = "guest";
+
+/// Close code of clients older than the minimum supported version.
+const UPGRADE_REQUIRED: CloseCode = CloseCode::Other(4426);
 
 thread_local! {
-    static SERVER: RefCell<Server> = RefCell::new(Server::new());
+    pub static SERVER: RefCell<Server> = RefCell::new(Server::new());
 }
 
 #[derive(Clone)]
 pub struct BoardContext {
     pub board_name: String,
     pub board_client_id: u8,
+    pub role: Role,
+    /// Token of the last `ResumeToken` sent to the client.
+    pub resume_token: u64,
+}
+
+/// Join request of this connection waiting for approval of the owner.
+#[derive(Clone)]
+pub struct PendingJoin {
+    pub board_name: String,
+    pub request_id: u16,
 }
 
 #[derive(Clone)]
 pub struct Client {
-    pub out: Sender,
+    pub out: Out,
+    pub outbox: Arc<Outbox>,
     pub authenticated_user: Option<User>,
     pub board_context: Option<BoardContext>,
+    pub pending_join: Option<PendingJoin>,
+    pub last_activity: Instant,
+    pub idle: bool,
+    pub connection_id: u32,
+    pub message_count: u32,
+    /// Frames received before joining a board, written to the capture
+    /// when the board turns out to be recorded.
+    pub preamble: Vec<Record>,
+    pub locale: Locale,
+    /// Sequence last acknowledged by `AckWindow`.
+    pub acked: u32,
+    /// Application version reported in `ClientHello`.
+    pub client_version: Option<String>,
+    /// Connection of the server to itself driving the housekeeping timer.
+    pub housekeeping: bool,
 }
 
 impl Handler for Client {
+    fn on_request(&mut self, req: &Request) -> Result<Response, Error> {
+        if let Some(header) = req.header("accept-language") {
+            self.locale = Locale::from_accept_language(&String::from_utf8_lossy(header));
+        }
+
+        match SERVER.with(|s| http::handle(&mut s.borrow_mut(), req)) {
+            Some(response) => Ok(response),
+            None => Response::from_request(req),
+        }
+    }
+
     fn on_message(&mut self, msg: Message) -> Result<(), Error> {
-        match msg {
-            Message::Text(_) => return self.out.close_with_reason(CloseCode::Invalid, "expected binary"),
-            Message::Binary(t) => self.handle_binary_msg(t)
+        self.message_count = self.message_count.wrapping_add(1);
+        request::set_current(Some(self.request_id()));
+
+        /* a panic must not take down the other connections of this thread */
+        let result = match msg {
+            Message::Text(_) => self.close(CloseCode::Invalid, "expected binary"),
+            Message::Binary(t) => {
+                let received = clock::now_millis();
+                let result = match panic::catch_unwind(AssertUnwindSafe(|| self.handle_binary_msg(&t))) {
+                    Ok(result) => result,
+                    Err(_) => {
+                        let panics = HANDLER_PANICS.fetch_add(1, Ordering::Relaxed) + 1;
+                        error!("Handler panicked while handling message ({} panics total)", panics);
+                        self.quarantine(&t, "handler panicked");
+                        self.close(CloseCode::Error, "internal error")
+                    }
+                };
+                self.record(received, t);
+                result
+            }
+        };
+
+        request::set_current(None);
+        result
+    }
+
+    fn on_timeout(&mut self, event: Token) -> Result<(), Error> {
+        /* timers run on the same event loop as messages */
+        match panic::catch_unwind(AssertUnwindSafe(|| self.handle_timeout(event))) {
+            Ok(result) => result,
+            Err(_) => {
+                let panics = HANDLER_PANICS.fetch_add(1, Ordering::Relaxed) + 1;
+                error!("Handler panicked while handling timeout {:?} ({} panics total)", event, panics);
+                self.close(CloseCode::Error, "internal error")
+            }
         }
     }
 
-    fn on_close(&mut self, _code: CloseCode, _reason: &str) {
+    fn on_open(&mut self, _: Handshake) -> Result<(), Error> {
+        if self.housekeeping {
+            return self.out.timeout(0, housekeeping::TICK);
+        }
+        Ok(())
+    }
+
+    fn on_close(&mut self, code: CloseCode, reason: &str) {
+        if self.housekeeping {
+            return error!("Housekeeping connection closed, boards are no longer swept: {}", reason);
+        }
+
         match code {
             CloseCode::Normal => info!("The client is done with the connection."),
             CloseCode::Away => info!("The client is leaving the site."),
             _ => warn!("The client encountered an error: {}", reason),
         }
+
+        /* the user id stays reserved for a resume until the window passes */
+        let connection_id = self.connection_id;
+        self.with_board(|b| b.remove_client(connection_id));
     }
 }
 
 impl Client {
-    fn handle_binary_msg(&mut self, t: Vec<u8>) -> Result<(), Error> {
+    pub fn new(out: Out) -> Self {
+        let outbox = Arc::new(Outbox::new(out));
+        Client {
+            out: outbox.clone(),
+            outbox,
+            authenticated_user: None,
+            board_context: None,
+            pending_join: None,
+            last_activity: Instant::now(),
+            idle: false,
+            connection_id: request::next_connection_id(),
+            message_count: 0,
+            preamble: vec![],
+            locale: Locale::En,
+            acked: 0,
+            client_version: None,
+            housekeeping: false,
+        }
+    }
+
+    pub fn housekeeper(out: Out) -> Self {
+        Client { housekeeping: true, ..Client::new(out) }
+    }
+
+    fn handle_binary_msg(&mut self, t: &Vec<u8>) -> Result<(), Error> {
+        let received = clock::now_millis();
         let msg: ObMessage = match from_bytes(t.as_slice()) {
             Ok(t) => t,
-            Err(_) => return self.out.close_with_reason(CloseCode::Error, "invalid message"),
+            Err(_) => {
+                self.quarantine(t, "invalid message");
+                return self.close(CloseCode::Error, "invalid message");
+            }
         };
 
+        /* clock sync is allowed in any state */
+        if let ObMessage::TimeSync(t) = msg {
+            return self.handle_time_sync(t, received);
+        }
+
+        if let ObMessage::ClientHello(t) = msg {
+            return self.handle_client_hello(t);
+        }
+
+        /* a draining instance only serves clients already in a board */
+        if self.board_context.is_none() && msg.is_join() {
+            let draining = SERVER.with(|x| {
+                let server = x.borrow();
+                if server.draining { Some(server.drain_target.clone()) } else { None }
+            });
+            if let Some(target) = draining {
+                return self.redirect(target);
+            }
+        }
+
         /* check auth */
         if self.authenticated_user.is_none() {
             return self.ensure_auth(msg);
@@ -59,94 +248,1156 @@ impl Client {
         }
 
         /* handler other cases */
-        self.handle_in_board_msg(msg, &t)
+        if !self.with_board(|b| b.tracing).unwrap_or(false) {
+            return self.handle_in_board_msg(msg, t);
+        }
+
+        let dump = format!("{:?}", msg);
+        let started = Instant::now();
+        let result = self.handle_in_board_msg(msg, t);
+        info!(target: TRACE_TARGET, "board={} user={} bytes={} took={:?} result={:?} {}",
+              self.board_context.as_ref().map(|x| x.board_name.as_str()).unwrap_or("-"), self.username(), t.len(), started.elapsed(), result.is_ok(), dump);
+        result
     }
 
     fn ensure_auth(&mut self, msg: ObMessage) -> Result<(), Error> {
+        let version = self.client_version.as_ref().map(|x| x.as_str());
+        if !SERVER.with(|x| x.borrow().config.supports_client(version)) {
+            return self.close(UPGRADE_REQUIRED, "upgrade required");
+        }
+
         match msg {
-            ObMessage::Auth(t) => match auth(t) {
-                None => return self.out.close_with_reason(CloseCode::Error, "invalid auth"),
+            ObMessage::Auth(t) => match SERVER.with(|x| auth(t, &x.borrow().config)) {
+                None => return self.close(CloseCode::Error, "invalid auth"),
                 Some(t) => {
+                    info!("Client {} ({}) authenticated successfully (version {})", t.username, t.user_id.as_ref().map(|x| x.as_str()).unwrap_or("unverified"), self.client_version.as_ref().map(|x| x.as_str()).unwrap_or("unknown"));
+                    let identity = match t.guest {
+                        true => Some(to_bytes(&ObMessage::GuestIdentity(GuestIdentity { username: &t.username })).unwrap()),
+                        false => None,
+                    };
                     self.authenticated_user = Some(t);
-                    info!("Client {} authenticated successfully", t.username);
-                    Ok(())
+                    match identity {
+                        Some(identity) => self.out.send(identity),
+                        None => Ok(()),
+                    }
                 }
             },
-            _ => return self.out.close_with_reason(CloseCode::Error, "auth expected"),
+            ObMessage::JoinView(t) => self.handle_join_view(t),
+            _ => return self.close(CloseCode::Error, "auth expected"),
         }
     }
 
+    /// The new token has to belong to the same user, only its expiry and
+    /// claims are taken over.
+    fn handle_reauth(&mut self, t: ReAuth) -> Result<(), Error> {
+        let user = match SERVER.with(|x| auth(Auth { jwt_token: t.jwt_token }, &x.borrow().config)) {
+            Some(t) => t,
+            None => return self.close(CloseCode::Policy, "invalid auth"),
+        };
+
+        let current = self.authenticated_user.as_ref().unwrap();
+        if user.guest || user.user_id != current.user_id || (user.user_id.is_none() && user.username != current.username) {
+            return self.close(CloseCode::Policy, "token of another user");
+        }
+
+        info!("Client {} refreshed its token, expires at {:?}", current.username, user.expires_at);
+        let current = self.authenticated_user.as_mut().unwrap();
+        current.expires_at = user.expires_at;
+        current.claims = user.claims;
+        current.plan = user.plan;
+        Ok(())
+    }
+
     fn ensure_in_board(&mut self, msg: ObMessage) -> Result<(), Error> {
         match msg {
             ObMessage::Join(t) => self.handle_board_join(t),
             ObMessage::Create(t) => self.handle_board_create(t),
-            _ => return self.out.close_with_reason(CloseCode::Error, "auth expected"),
+            ObMessage::CreateFromTemplate(t) => self.handle_board_create_from_template(t),
+            ObMessage::JoinInvite(t) => self.handle_board_join_invite(t),
+            ObMessage::RequestJoin(t) => self.handle_request_join(t),
+            ObMessage::JoinView(t) => self.handle_join_view(t),
+            ObMessage::Resume(t) => self.handle_resume(t),
+            ObMessage::AdminBroadcast(t) => self.handle_admin_broadcast(t),
+            ObMessage::AdminDeleteBoard(t) => self.handle_admin_delete_board(t),
+            _ => return self.close(CloseCode::Error, "auth expected"),
+        }
+    }
+
+    fn handle_client_hello(&mut self, t: ClientHello) -> Result<(), Error> {
+        if self.client_version.is_some() {
+            return self.close(CloseCode::Error, "client hello already sent");
         }
+
+        info!("Client reports version {}", t.version);
+        self.client_version = Some(t.version.to_string());
+        Ok(())
     }
 
     fn handle_board_create(&mut self, t: Create) -> Result<(), Error> {
+        self.create_board(t.template_id, t.name, &[])
+    }
+
+    fn handle_board_create_from_template(&mut self, t: CreateFromTemplate) -> Result<(), Error> {
+        self.create_board(t.template_id, t.name, &t.variables)
+    }
+
+    fn create_board(&mut self, template_id: u64, name: &str, variables: &[TemplateVariable]) -> Result<(), Error> {
         return SERVER.with(|x| {
             let mut server = x.borrow_mut();
 
-            if server.has_board(t.name) { return self.out.close_with_reason(CloseCode::Error, "board already exists"); }
-
-            self.board_context = Some(BoardContext {
-                board_client_id: 0,
-                board_name: String::from(t.name),
-            });
+            if self.authenticated_user.as_ref().unwrap().guest { return self.close(CloseCode::Policy, "guests cannot create boards"); }
+            if server.has_board(name) { return self.close(CloseCode::Error, "board already exists"); }
+            if server.exceeds_board_quota(self.authenticated_user.as_ref().unwrap()) { return self.close(CloseCode::Policy, "board quota exceeded"); }
 
-            info!("Client {} is creating board {} (template_id={})", self.authenticated_user.unwrap().username, t.name, t.template_id);
-            server.create(String::from(t.name))
-                .add_client(self)
-                .map_err(|_| ws::Error::new(ErrorKind::Internal, "cannot add client to board"))
+            info!("Client {} is creating board {} (template_id={})", self.authenticated_user.as_ref().unwrap().username, name, template_id);
+            let user = self.authenticated_user.clone().unwrap();
+            let variables = variables.iter().map(|x| (x.name.to_string(), x.value.to_string())).collect();
+            let board = server.create_from_template(name, &user, template_id, variables);
+            self.enter_board(board, name, Role::Owner)
         });
     }
 
     fn handle_board_join(&mut self, t: Join) -> Result<(), Error> {
         return SERVER.with(|x| {
             let mut server = x.borrow_mut();
-            match server.find(t.name) {
+            let role = server.join_role(t.name, self.authenticated_user.as_ref().unwrap());
+            match (server.find(t.name), role) {
+                (Some(ref b), _) if b.is_full() => self.close(CloseCode::Policy, "board is full"),
+                (Some(b), Some(role)) => {
+                    info!("Client {} is joining board {} as {:?}", self.authenticated_user.as_ref().unwrap().username, t.name, role);
+                    self.enter_board(b, t.name, role)
+                }
+                (Some(_), None) => self.close(CloseCode::Policy, "board is private"),
+                (None, _) => self.close(CloseCode::Error, "board not found"),
+            }
+        });
+    }
+
+    /// Invites grant access to the board regardless of its access control.
+    fn handle_board_join_invite(&mut self, t: JoinInvite) -> Result<(), Error> {
+        return SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let invite = match Invite::verify(t.token, &server.config.invite_secret) {
+                Ok(t) => t,
+                Err(_) => return self.close(CloseCode::Policy, "invalid invite"),
+            };
+
+            match server.find(&invite.board_name) {
+                Some(ref b) if b.is_full() => self.close(CloseCode::Policy, "board is full"),
                 Some(b) => {
-                    info!("Client {} is creating board {}", self.authenticated_user.unwrap().username, t.name);
-                    b.add_client(self)
-                        .map_err(|_| ws::Error::new(ErrorKind::Internal, "cannot add client to board"))
+                    if b.use_invite(&invite).is_err() {
+                        return self.close(CloseCode::Policy, "invite used up");
+                    }
+
+                    info!("Client {} is joining board {} using invite {}", self.username(), invite.board_name, invite.nonce);
+                    self.enter_board(b, &invite.board_name, invite.role)
+                }
+                None => self.close(CloseCode::Error, "board not found"),
+            }
+        });
+    }
+
+    /// View tokens grant spectator access even without authentication, in
+    /// which case the connection is treated as a guest user.
+    fn handle_join_view(&mut self, t: JoinView) -> Result<(), Error> {
+        return SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let view_token = match ViewToken::verify(t.token, &server.config.invite_secret) {
+                Ok(t) => t,
+                Err(_) => return self.close(CloseCode::Policy, "invalid view token"),
+            };
+
+            match server.find(&view_token.board_name) {
+                Some(ref b) if b.is_full() => self.close(CloseCode::Policy, "board is full"),
+                Some(b) => {
+                    if self.authenticated_user.is_none() {
+                        self.authenticated_user = Some(User { username: GUEST_USERNAME.to_string(), display_name: GUEST_USERNAME.to_string(), user_id: None, plan: Plan::Free, guest: true, claims: HashMap::new(), expires_at: None, admin: false });
+                    }
+
+                    info!("Client {} is viewing board {} using view token", self.username(), view_token.board_name);
+                    self.enter_board(b, &view_token.board_name, Role::Viewer)
+                }
+                None => self.close(CloseCode::Error, "board not found"),
+            }
+        });
+    }
+
+    fn handle_request_join(&mut self, t: RequestJoin) -> Result<(), Error> {
+        let username = self.username();
+        return SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            match server.find(t.name) {
+                Some(b) => match b.request_join(&username, &self.out) {
+                    Ok(request_id) => {
+                        info!("Client {} requested to join board {} (request_id={})", username, t.name, request_id);
+                        self.pending_join = Some(PendingJoin { board_name: String::from(t.name), request_id });
+                        Ok(())
+                    }
+                    Err(_) => self.close(CloseCode::Policy, "no owner online"),
+                },
+                None => self.close(CloseCode::Error, "board not found"),
+            }
+        });
+    }
+
+    fn handle_join_response(&mut self) -> Result<(), Error> {
+        let pending = match self.pending_join.take() {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        return SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            match server.find(&pending.board_name) {
+                Some(b) => match b.take_join_request(pending.request_id) {
+                    Some(true) if b.is_full() => self.close(CloseCode::Policy, "board is full"),
+                    Some(true) => self.enter_board(b, &pending.board_name, Role::Editor),
+                    _ => self.close(CloseCode::Policy, "join request denied"),
+                },
+                None => self.close(CloseCode::Error, "board not found"),
+            }
+        });
+    }
+
+    fn enter_board(&mut self, board: &mut Board, board_name: &str, role: Role) -> Result<(), Error> {
+        let moving = self.board_context.is_some();
+        self.board_context = Some(BoardContext {
+            board_client_id: 0,
+            board_name: String::from(board_name),
+            role,
+            resume_token: 0,
+        });
+
+        /* clients joining in a burst receive their history one after another */
+        let mut backfill_delay = HISTORY_BACKFILL_DELAY_MS;
+        if features::enabled(Feature::JoinThrottle, &board.owner) {
+            backfill_delay += board.reserve_history_slot().as_millis() as u64;
+        }
+        board.add_client(self)
+            .map_err(|_| ws::Error::new(ErrorKind::Internal, "cannot add client to board"))
+            .and_then(|_| self.out.timeout(backfill_delay, HISTORY_BACKFILL))
+            .and_then(|_| self.out.timeout(0, PROFILE))?;
+
+        /* periodic checks keep running when the connection moves between boards */
+        if moving {
+            return Ok(());
+        }
+        self.out.timeout(IDLE_CHECK_INTERVAL_MS, IDLE_CHECK)
+            .and_then(|_| self.out.timeout(ACK_INTERVAL_MS, ACK_WINDOW))
+    }
+
+    /// Reattaches the connection under the user id and role it had before
+    /// the disconnect and sends only the history it missed.
+    fn handle_resume(&mut self, t: Resume) -> Result<(), Error> {
+        let username = self.username();
+        return SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            /* access may have been revoked in the meantime */
+            let role = server.join_role(t.board_name, self.authenticated_user.as_ref().unwrap());
+            let b = match server.find(t.board_name) {
+                Some(ref b) if b.is_full() => return self.close(CloseCode::Policy, "board is full"),
+                Some(b) => b,
+                None => return self.close(CloseCode::Error, "board not found"),
+            };
+
+            if role.is_none() {
+                return self.close(CloseCode::Policy, "cannot resume");
+            }
+
+            let departure = match b.take_departure(t.token, &username) {
+                Ok(t) => t,
+                Err(e) => {
+                    info!("Client {} cannot resume session in board {}: {}", username, t.board_name, e);
+                    return self.close(CloseCode::Policy, "cannot resume");
                 }
-                None => self.out.close_with_reason(CloseCode::Error, "board not found"),
+            };
+
+            info!("Client {} is resuming session in board {} as user_id {}", username, t.board_name, departure.user_id);
+            self.board_context = Some(BoardContext {
+                board_client_id: departure.user_id,
+                board_name: String::from(t.board_name),
+                role: departure.role,
+                resume_token: 0,
+            });
+            b.attach(self, departure.user_id)
+                .map_err(|_| ws::Error::new(ErrorKind::Internal, "cannot add client to board"))
+                .and_then(|_| self.out.timeout(0, PROFILE))?;
+
+            match b.history_since(departure.step_id) {
+                Some(missed) => send_history(&missed, &self.out)
+                    .and_then(|_| send_history(&b.history_sent(departure.user_id), &self.out))
+                    .map_err(|_| ws::Error::new(ErrorKind::Internal, "cannot send missed history"))?,
+                None => self.out.timeout(HISTORY_BACKFILL_DELAY_MS, HISTORY_BACKFILL)?,
+            }
+            self.out.timeout(IDLE_CHECK_INTERVAL_MS, IDLE_CHECK)
+                .and_then(|_| self.out.timeout(ACK_INTERVAL_MS, ACK_WINDOW))
+        });
+    }
+
+    /// Moves the connection to the target board of the portal under the
+    /// same rules as `Join`. Viewers stay viewers in the target board.
+    fn handle_enter_portal(&mut self, t: EnterPortal) -> Result<(), Error> {
+        let target = match self.with_board(|b| b.portal_target(t.object_id)) {
+            Some(Some(t)) => t,
+            _ => return self.close(CloseCode::Error, "portal does not exist"),
+        };
+
+        let username = self.username();
+        let current = self.board_context.clone().unwrap();
+        return SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let role = server.join_role(&target, self.authenticated_user.as_ref().unwrap());
+            let denied = match server.find(&target) {
+                None => Some("board not found"),
+                Some(ref b) if b.is_full() => Some("board is full"),
+                Some(_) if role.is_none() => Some("board is private"),
+                Some(_) => None,
+            };
+            if let Some(reason) = denied {
+                info!("Client {} cannot enter portal to {}: {}", username, target, reason);
+                return self.out.send(to_bytes(&ObMessage::ServerMessage(ServerMessage { message: self.locale.translate(reason) })).unwrap());
+            }
+
+            info!("Client {} is moving from board {} to {} through a portal", username, current.board_name, target);
+            if let Some(b) = server.find(&current.board_name) {
+                b.remove_client(self.connection_id);
             }
+            let b = server.find(&target).unwrap();
+            let role = match (role.unwrap(), current.role) {
+                (Role::Owner, _) => Role::Owner,
+                (_, Role::Viewer) => Role::Viewer,
+                (role, _) => role,
+            };
+            self.enter_board(b, &target, role)
         });
     }
 
+    fn handle_timeout(&mut self, event: Token) -> Result<(), Error> {
+        match event {
+            HISTORY_BACKFILL => self.handle_history_backfill(),
+            JOIN_RESPONSE => self.handle_join_response(),
+            IDLE_CHECK => self.handle_idle_check(),
+            ACK_WINDOW => self.handle_ack_window(),
+            PROFILE => self.handle_profile(),
+            outbox::FLUSH => self.outbox.flush(),
+            housekeeping::TICK => self.handle_housekeeping(),
+            _ => Ok(())
+        }
+    }
+
+    /// History is queued right away so nothing published later can reach
+    /// the client before it, the outbox then sends it in small batches.
+    fn handle_history_backfill(&mut self) -> Result<(), Error> {
+        let user_id = match &self.board_context {
+            Some(t) => t.board_client_id,
+            None => return Ok(()),
+        };
+        let (history, held) = match self.with_board(|b| (b.history_frames(), b.history_sent(user_id))) {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        send_history(&history, &self.out)
+            .and_then(|_| send_history(&held, &self.out))
+            .map_err(|_| ws::Error::new(ErrorKind::Internal, "cannot send history"))
+    }
+
+    /// Profiles and avatars are fetched by the job worker so a slow
+    /// profile service or avatar host never blocks the event loop.
+    fn handle_profile(&mut self) -> Result<(), Error> {
+        let (cache, avatars) = SERVER.with(|x| (x.borrow().profiles.clone(), x.borrow().avatars.clone()));
+        let (cache, user_id) = match (cache, self.authenticated_user.as_ref().and_then(|x| x.user_id.clone())) {
+            (Some(cache), Some(user_id)) => (cache, user_id),
+            _ => return Ok(()),
+        };
+
+        let profile = cache.cached(&user_id);
+        match (profile, &avatars) {
+            (Some(Some(mut profile)), avatars) if avatars.as_ref().map(|x| x.is_cached(&user_id)).unwrap_or(true) => {
+                if let Some(avatars) = avatars {
+                    profile.avatar_url = avatars.url(&user_id);
+                }
+                let board_client_id = self.board_context.as_ref().map(|x| x.board_client_id).unwrap_or(0);
+                self.with_board(|b| b.set_profile(board_client_id, &profile));
+            }
+            /* unknown to the profile service, the username is shown instead */
+            (Some(None), _) => {}
+            (profile, _) => {
+                let out = self.out.clone();
+                let name = format!("profile of user {}", user_id);
+                SERVER.with(|x| x.borrow_mut().jobs.submit(&name, move |_| {
+                    let result = match profile {
+                        Some(Some(profile)) => Ok(profile),
+                        _ => cache.resolve(&user_id),
+                    };
+                    let result = match (result, avatars) {
+                        (Ok(profile), Some(avatars)) => avatars.fetch(&user_id, &profile.avatar_url)
+                            .map_err(|e| format!("cannot proxy avatar: {}", e)),
+                        (result, _) => result.map(|_| ()),
+                    };
+                    /* the connection may be gone by now */
+                    let _ = out.timeout(0, PROFILE);
+                    result
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_ack_window(&mut self) -> Result<(), Error> {
+        if self.acked != self.message_count {
+            self.acked = self.message_count;
+            self.out.send(to_bytes(&ObMessage::AckWindow(AckWindow { sequence: self.acked })).unwrap())?;
+        }
+
+        self.out.timeout(ACK_INTERVAL_MS, ACK_WINDOW)
+    }
+
+    fn handle_housekeeping(&mut self) -> Result<(), Error> {
+        SERVER.with(|x| housekeeping::run(&mut x.borrow_mut()));
+        self.out.timeout(housekeeping::INTERVAL_MS, housekeeping::TICK)
+    }
+
+    fn handle_idle_check(&mut self) -> Result<(), Error> {
+        if self.authenticated_user.as_ref().map(is_expired).unwrap_or(false) {
+            return self.close(CloseCode::Policy, "token expired");
+        }
+
+        /* the board was deleted and the connection is closing */
+        let user_id = match &self.board_context {
+            Some(t) => t.board_client_id,
+            None => return Ok(()),
+        };
+        if !self.idle && self.last_activity.elapsed() >= IDLE_AFTER {
+            self.idle = true;
+            self.with_board(|b| {
+                if b.presence(user_id) == PresenceState::Active {
+                    b.set_presence(user_id, PresenceState::Away);
+                }
+            });
+        }
+
+        self.out.timeout(IDLE_CHECK_INTERVAL_MS, IDLE_CHECK)
+    }
+
     fn handle_in_board_msg(&mut self, msg: ObMessage, t: &Vec<u8>) -> Result<(), Error> {
+        if msg.is_write() && self.board_context.as_ref().unwrap().role == Role::Viewer {
+            return self.close(CloseCode::Policy, "read-only access");
+        }
+
+        if msg.is_mutation() {
+            if let Some(Some(lock)) = self.with_board(|b| if b.is_locked() { Some(b.lock_message()) } else { None }) {
+                return self.out.send(lock);
+            }
+        }
+
+        let user_id = self.board_context.as_ref().unwrap().board_client_id;
+        if !matches!(msg, ObMessage::Ping(_)) {
+            self.last_activity = Instant::now();
+            if self.idle {
+                self.idle = false;
+                self.with_board(|b| {
+                    if b.presence(user_id) == PresenceState::Away {
+                        b.set_presence(user_id, PresenceState::Active);
+                    }
+                });
+            }
+        }
+
+        let reliability = msg.reliability();
         match msg {
-            ObMessage::Auth(_) => self.out.close_with_reason(CloseCode::Error, "already authenticated"),
-            ObMessage::Join(_) => self.out.close_with_reason(CloseCode::Error, "already joined a board"),
-            ObMessage::BoardConfiguration(_) => self.out.close_with_reason(CloseCode::Error, "board configuration invalid atm"),
-            ObMessage::History(_) => self.out.close_with_reason(CloseCode::Error, "history invalid atm"),
-            ObMessage::ServerMessage(_) => self.out.close_with_reason(CloseCode::Error, "server message invalid atm"),
-            ObMessage::UserJoin(_) => self.out.close_with_reason(CloseCode::Error, "user join invalid atm"),
-            ObMessage::UserLeave(_) => self.out.close_with_reason(CloseCode::Error, "user leave invalid atm"),
-            ObMessage::Create(_) => self.out.close_with_reason(CloseCode::Error, "already joined a board"),
+            ObMessage::Auth(_) => self.close(CloseCode::Error, "already authenticated"),
+            ObMessage::Join(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::BoardConfiguration(_) => self.close(CloseCode::Error, "board configuration invalid atm"),
+            ObMessage::History(_) => self.close(CloseCode::Error, "history invalid atm"),
+            ObMessage::ServerMessage(_) => self.close(CloseCode::Error, "server message invalid atm"),
+            ObMessage::UserJoin(_) => self.close(CloseCode::Error, "user join invalid atm"),
+            ObMessage::UserLeave(_) => self.close(CloseCode::Error, "user leave invalid atm"),
+            ObMessage::Create(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::CreateFromTemplate(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::JoinInvite(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::Invite(_) => self.close(CloseCode::Error, "invite invalid atm"),
+            ObMessage::CreateInvite(c) => self.handle_create_invite(c),
+            ObMessage::ViewToken(_) => self.close(CloseCode::Error, "view token invalid atm"),
+            ObMessage::JoinView(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::CreateViewToken(c) => self.handle_create_view_token(c),
+            ObMessage::Idempotent(c) => self.handle_idempotent(c),
+            ObMessage::Provisional(c) => self.handle_provisional(c),
+            ObMessage::BoardLock(_) => self.close(CloseCode::Error, "board lock invalid atm"),
+            ObMessage::RequestExport(_) => self.handle_request_export(),
+            ObMessage::ExportProgress(_) => self.close(CloseCode::Error, "export progress invalid atm"),
+            ObMessage::ExportReady(_) => self.close(CloseCode::Error, "export ready invalid atm"),
+            ObMessage::Reconnect(_) => self.close(CloseCode::Error, "reconnect invalid atm"),
+            ObMessage::ClientHello(_) => self.close(CloseCode::Error, "client hello invalid atm"),
+            ObMessage::Search(s) => self.handle_search(s),
+            ObMessage::SearchResults(_) => self.close(CloseCode::Error, "search results invalid atm"),
+            ObMessage::StepAssigned(_) => self.close(CloseCode::Error, "step assigned invalid atm"),
+            ObMessage::RequestJoin(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::JoinRequest(_) => self.close(CloseCode::Error, "join request invalid atm"),
+            ObMessage::SetPrivate(p) => self.handle_set_private(p),
+            ObMessage::RespondJoin(r) => self.handle_respond_join(r),
+            ObMessage::PollResults(_) => self.close(CloseCode::Error, "poll results invalid atm"),
+            ObMessage::CreatePoll(p) => self.handle_create_poll(p),
+            ObMessage::Vote(v) => self.handle_vote(v),
+            ObMessage::ClosePoll(c) => self.handle_close_poll(c),
+            ObMessage::PlaceVote(v) => self.handle_place_vote(v),
+            ObMessage::Stamp(s) => self.handle_stamp(s),
+            ObMessage::SetGrid(g) => self.handle_set_grid(g),
+            ObMessage::SetBackground(b) => self.handle_set_background(b),
+            ObMessage::SetPalettePreset(p) => self.handle_set_palette_preset(p),
+            ObMessage::Connector(c) => self.handle_connector(c),
+            ObMessage::CreateFrame(f) => self.handle_create_frame(f),
+            ObMessage::Mention(_) => self.close(CloseCode::Error, "mention invalid atm"),
+            ObMessage::MissedEvents(_) => self.close(CloseCode::Error, "missed events invalid atm"),
+            ObMessage::CreateBookmark(b) => {
+                self.with_board(|x| x.create_bookmark(b));
+                Ok(())
+            }
+            ObMessage::JumpToFrame(j) => self.handle_jump_to_frame(j),
+            ObMessage::Viewport(v) => {
+                self.with_board(|b| b.set_viewport(user_id, v.bounds));
+                Ok(())
+            }
+            ObMessage::RequestUserViewport(r) => self.handle_request_user_viewport(r),
+            ObMessage::Follow(f) => self.handle_follow(f),
             ObMessage::Ping(_) => { Ok(()) }
             ObMessage::Step(_) => { Ok(()) }
             ObMessage::Undo(_) => { Ok(()) }
-            ObMessage::Draw(_) | ObMessage::CursorMove(_) | ObMessage::Fill(_) | ObMessage::Image(_) | ObMessage::Text(_) => self.broadcast_to_board(t),
+            ObMessage::Minimap(_) => self.close(CloseCode::Error, "minimap invalid atm"),
+            ObMessage::Draw(d) => self.handle_draw(d, t),
+            ObMessage::Fill(f) => self.handle_fill(f, t),
+            ObMessage::CurveStroke(c) => self.handle_curve_stroke(c, t),
+            ObMessage::ClearRegion(c) => self.handle_clear_region(c, t),
+            ObMessage::ClearAll(_) => self.handle_clear_all(t),
+            ObMessage::GuestIdentity(_) => self.close(CloseCode::Error, "guest identity invalid atm"),
+            ObMessage::Group(g) => self.handle_group(g),
+            ObMessage::CopyObjects(c) => self.handle_copy_objects(c),
+            ObMessage::Clipboard(_) => self.close(CloseCode::Error, "clipboard invalid atm"),
+            ObMessage::PasteObjects(p) => self.handle_paste_objects(p),
+            ObMessage::Portal(p) => {
+                self.with_board(|b| b.create_portal(p));
+                Ok(())
+            }
+            ObMessage::EnterPortal(p) => self.handle_enter_portal(p),
+            ObMessage::ReAuth(r) => self.handle_reauth(r),
+            ObMessage::ResumeToken(_) => self.close(CloseCode::Error, "resume token invalid atm"),
+            ObMessage::UserProfile(_) => self.close(CloseCode::Error, "user profile invalid atm"),
+            ObMessage::SetAccess(s) => self.handle_set_access(s),
+            ObMessage::RequestAccessList(_) => self.handle_request_access_list(),
+            ObMessage::AccessList(_) => self.close(CloseCode::Error, "access list invalid atm"),
+            ObMessage::AdminBroadcast(t) => self.handle_admin_broadcast(t),
+            ObMessage::AdminDeleteBoard(t) => self.handle_admin_delete_board(t),
+            ObMessage::RequestTimeline(_) => match self.with_board(|b| b.timeline_message()) {
+                Some(Ok(timeline)) => self.out.send(timeline),
+                _ => Ok(()),
+            },
+            ObMessage::Timeline(_) => self.close(CloseCode::Error, "timeline invalid atm"),
+            ObMessage::Resume(_) => self.close(CloseCode::Error, "already joined a board"),
+            ObMessage::RequestRegion(r) => self.handle_request_region(r),
+            ObMessage::RegionPatch(_) => self.close(CloseCode::Error, "region patch invalid atm"),
+            ObMessage::SetNotifications(n) => {
+                self.with_board(|b| b.set_notifications(user_id, n.flags));
+                Ok(())
+            }
+            ObMessage::TimeSync(_) => Ok(()),
+            ObMessage::AckWindow(_) => self.close(CloseCode::Error, "ack window invalid atm"),
+            ObMessage::AccessibilityEvent(_) => self.close(CloseCode::Error, "accessibility event invalid atm"),
+            ObMessage::SetAccessibility(a) => {
+                for message in self.with_board(|b| b.set_accessibility(user_id, a.enabled)).unwrap_or_default() {
+                    self.out.send(message)?;
+                }
+                Ok(())
+            }
+            ObMessage::SnapshotDelta(_) => self.close(CloseCode::Error, "snapshot delta invalid atm"),
+            ObMessage::RequestSnapshot(_) => {
+                for message in self.with_board(|b| b.snapshot_delta(user_id)).unwrap_or_default() {
+                    self.out.send(message)?;
+                }
+                Ok(())
+            }
+            ObMessage::Typing(t) => {
+                self.with_board(|b| b.typing(user_id, t));
+                Ok(())
+            }
+            ObMessage::SetPresence(p) => {
+                self.with_board(|b| b.set_presence(user_id, p.state));
+                Ok(())
+            }
+            ObMessage::CursorMove(_) => self.broadcast_to_board(t, NotificationFlags::CURSORS, reliability),
+            ObMessage::Image(_) => self.broadcast_to_board(t, NotificationFlags::empty(), reliability),
+            ObMessage::Text(x) => self.handle_text(x, reliability),
         }
     }
 
-    fn broadcast_to_board(&mut self, t: &Vec<u8>) -> Result<(), Error> {
-        SERVER.with(|x| {
+    fn handle_create_poll(&mut self, t: CreatePoll) -> Result<(), Error> {
+        let username = self.username();
+        match self.with_board(|b| b.create_poll(&username, t)) {
+            Some(Err(_)) => self.close(CloseCode::Error, "invalid poll"),
+            _ => Ok(())
+        }
+    }
+
+    fn handle_vote(&mut self, t: Vote) -> Result<(), Error> {
+        let username = self.username();
+        if let Some(Err(e)) = self.with_board(|b| b.vote(&username, t)) {
+            warn!("Vote of client {} rejected: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_close_poll(&mut self, t: ClosePoll) -> Result<(), Error> {
+        let username = self.username();
+        if let Some(Err(e)) = self.with_board(|b| b.close_poll(&username, t)) {
+            warn!("Client {} cannot close poll: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_place_vote(&mut self, t: PlaceVote) -> Result<(), Error> {
+        let username = self.username();
+        let user_id = self.board_context.as_ref().unwrap().board_client_id;
+        if let Some(Err(e)) = self.with_board(|b| b.place_vote(&username, user_id, t)) {
+            warn!("Dot vote of client {} rejected: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_stamp(&mut self, t: Stamp) -> Result<(), Error> {
+        match self.with_board(|b| b.stamp(t)) {
+            Some(Err(_)) => self.close(CloseCode::Error, "invalid sticker"),
+            _ => Ok(())
+        }
+    }
+
+    fn handle_set_grid(&mut self, t: SetGrid) -> Result<(), Error> {
+        let username = self.acting_username();
+        if let Some(Err(e)) = self.with_board(|b| b.set_grid(&username, t)) {
+            warn!("Client {} cannot change grid: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_set_background(&mut self, t: SetBackground) -> Result<(), Error> {
+        let username = self.acting_username();
+        if let Some(Err(e)) = self.with_board(|b| b.set_background(&username, t)) {
+            warn!("Client {} cannot change background: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_set_palette_preset(&mut self, t: SetPalettePreset) -> Result<(), Error> {
+        let username = self.acting_username();
+        if let Some(Err(e)) = self.with_board(|b| b.set_palette_preset(&username, t)) {
+            warn!("Client {} cannot change palette: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_connector(&mut self, t: Connector) -> Result<(), Error> {
+        match self.with_board(|b| b.connect(t)) {
+            Some(Err(_)) => self.close(CloseCode::Error, "invalid connector"),
+            _ => Ok(())
+        }
+    }
+
+    fn handle_create_frame(&mut self, t: CreateFrame) -> Result<(), Error> {
+        self.with_board(|b| b.create_frame(t));
+        Ok(())
+    }
+
+    fn handle_copy_objects(&mut self, t: CopyObjects) -> Result<(), Error> {
+        match self.with_board(|b| b.copy_objects(&t.ids)) {
+            Some(Ok(clipboard)) => self.out.send(clipboard),
+            Some(Err(e)) => {
+                warn!("Client {} cannot copy objects: {}", self.username(), e);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn handle_paste_objects(&mut self, t: PasteObjects) -> Result<(), Error> {
+        let (username, display_name) = (self.username(), self.display_name());
+        if let Some(Err(e)) = self.with_board(|b| b.paste_objects(&t).map(|_| b.record_event(TimelineKind::Paste, &display_name, ""))) {
+            warn!("Client {} cannot paste objects: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_search(&mut self, t: Search) -> Result<(), Error> {
+        let hits = self.with_board(|b| b.search(t.query)).unwrap_or_default();
+        self.out.send(to_bytes(&ObMessage::SearchResults(SearchResults { hits })).unwrap())
+    }
+
+    fn handle_jump_to_frame(&mut self, t: JumpToFrame) -> Result<(), Error> {
+        let user_id = self.board_context.as_ref().unwrap().board_client_id;
+        match self.with_board(|b| b.jump_to_frame(user_id, t)) {
+            Some(Ok(viewport)) => self.out.send(viewport),
+            Some(Err(e)) => {
+                warn!("Client {} cannot jump to frame: {}", self.username(), e);
+                Ok(())
+            }
+            None => Ok(())
+        }
+    }
+
+    fn handle_request_user_viewport(&mut self, t: RequestUserViewport) -> Result<(), Error> {
+        match self.with_board(|b| b.user_viewport(t.user_id)) {
+            Some(Ok(viewport)) => self.out.send(viewport),
+            Some(Err(e)) => {
+                warn!("Client {} cannot jump to user: {}", self.username(), e);
+                Ok(())
+            }
+            None => Ok(())
+        }
+    }
+
+    fn handle_follow(&mut self, t: Follow) -> Result<(), Error> {
+        let user_id = self.board_context.as_ref().unwrap().board_client_id;
+        match self.with_board(|b| b.follow(user_id, t)) {
+            Some(Ok(Some(viewport))) => self.out.send(viewport),
+            Some(Err(e)) => {
+                warn!("Client {} cannot follow: {}", self.username(), e);
+                Ok(())
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn handle_text(&mut self, t: Text, reliability: Reliability) -> Result<(), Error> {
+        let user_id = self.board_context.as_ref().unwrap().board_client_id;
+        let text = normalize::text(t.text);
+        let t = Text { text: text.as_str(), ..t };
+        self.with_board(|b| {
+            b.index_text(&t);
+            b.notify_mentions(user_id, &t);
+        });
+        self.broadcast_to_board(&to_bytes(&ObMessage::Text(t)).unwrap(), NotificationFlags::empty(), reliability)
+    }
+
+    fn handle_draw(&mut self, d: Draw, t: &Vec<u8>) -> Result<(), Error> {
+        self.with_board(|b| b.draw(&d));
+        self.broadcast_to_board(t, NotificationFlags::empty(), Reliability::Reliable)
+    }
+
+    fn handle_fill(&mut self, f: Fill, t: &Vec<u8>) -> Result<(), Error> {
+        self.with_board(|b| b.fill(&f));
+        self.broadcast_to_board(t, NotificationFlags::empty(), Reliability::Reliable)
+    }
+
+    fn handle_curve_stroke(&mut self, c: CurveStroke, t: &Vec<u8>) -> Result<(), Error> {
+        match self.with_board(|b| b.curve(&c)) {
+            Some(Err(_)) => self.close(CloseCode::Error, "invalid curve stroke"),
+            _ => self.broadcast_to_board(t, NotificationFlags::empty(), Reliability::Reliable),
+        }
+    }
+
+    fn handle_clear_region(&mut self, c: ClearRegion, t: &Vec<u8>) -> Result<(), Error> {
+        self.with_board(|b| b.clear_region(&c));
+        self.broadcast_to_board(t, NotificationFlags::empty(), Reliability::Reliable)
+    }
+
+    fn handle_clear_all(&mut self, t: &Vec<u8>) -> Result<(), Error> {
+        let display_name = self.display_name();
+        self.with_board(|b| {
+            b.clear_all();
+            b.record_event(TimelineKind::Clear, &display_name, "");
+        });
+        self.broadcast_to_board(t, NotificationFlags::empty(), Reliability::Reliable)
+    }
+
+    fn handle_request_region(&mut self, t: RequestRegion) -> Result<(), Error> {
+        for patch in self.with_board(|b| b.region_patches(t)).unwrap_or_default() {
+            self.out.send(patch)?;
+        }
+        Ok(())
+    }
+
+    fn handle_create_invite(&mut self, t: CreateInvite) -> Result<(), Error> {
+        let username = self.acting_username();
+        let board_name = self.board_context.as_ref().unwrap().board_name.clone();
+        let token = SERVER.with(|x| {
             let mut server = x.borrow_mut();
+            let secret = server.config.invite_secret.clone();
+            server.find(&board_name).map(|b| b.create_invite(&username, &board_name, t, &secret))
+        });
+
+        match token {
+            Some(Ok(token)) => self.out.send(to_bytes(&ObMessage::Invite(InviteMessage { token: token.as_str() })).unwrap()),
+            Some(Err(e)) => {
+                warn!("Client {} cannot create invite: {}", username, e);
+                Ok(())
+            }
+            None => Ok(())
+        }
+    }
+
+    fn handle_idempotent(&mut self, t: Idempotent) -> Result<(), Error> {
+        let msg = match from_bytes::<ObMessage>(t.message) {
+            Ok(msg) if msg.is_mutation() => msg,
+            _ => return self.close(CloseCode::Error, "idempotent envelope must hold a mutation"),
+        };
+
+        let username = self.username();
+        if self.with_board(|b| b.use_idempotency_key(&username, t.key)).unwrap_or(false) {
+            info!("Client {} resent message with idempotency key {}, ignoring", username, t.key);
+            return Ok(());
+        }
+
+        self.handle_in_board_msg(msg, &t.message.to_vec())
+    }
 
-            self.board_context.as_ref().map(|x| {
-                let board = server.find(&x.board_name).unwrap();
+    /// Handles the wrapped mutation and tells the client which step it
+    /// became. Idempotent envelopes may be wrapped as well.
+    fn handle_provisional(&mut self, t: Provisional) -> Result<(), Error> {
+        let msg = match from_bytes::<ObMessage>(t.message) {
+            Ok(msg) if msg.is_mutation() || matches!(msg, ObMessage::Idempotent(_)) => msg,
+            _ => return self.close(CloseCode::Error, "provisional envelope must hold a mutation"),
+        };
+
+        let user_id = self.board_context.as_ref().unwrap().board_client_id;
+        let retried = |b: &mut Board| match features::enabled(Feature::DuplicateDetection, &b.owner) {
+            true => b.retried_step(user_id, t.provisional_id, t.message),
+            false => None,
+        };
+        if let Some(Some(step_id)) = self.with_board(retried) {
+            info!("Client {} retried provisional message {}, dropping", self.username(), t.provisional_id);
+            return self.out.send(to_bytes(&ObMessage::StepAssigned(StepAssigned { provisional_id: t.provisional_id, step_id })).unwrap());
+        }
 
-                if board.history_size != 0 {
-                    board.add_to_history(t);
+        let before = self.with_board(|b| b.last_step_id());
+        self.handle_in_board_msg(msg, &t.message.to_vec())?;
+        let after = self.with_board(|b| b.last_step_id());
+
+        let step_id = match (before, after) {
+            (Some(before), Some(after)) if before != after => Some(after),
+            _ => None,
+        };
+        self.with_board(|b| b.remember_step(user_id, t.provisional_id, t.message, step_id));
+        self.out.send(to_bytes(&ObMessage::StepAssigned(StepAssigned { provisional_id: t.provisional_id, step_id })).unwrap())
+    }
+
+    /// Whole group is decoded and checked against the board before any of
+    /// it is applied so invalid groups are rejected without leaving a
+    /// partial change behind.
+    fn handle_group(&mut self, t: Group) -> Result<(), Error> {
+        let mut messages = vec![];
+        let mut offset = 0;
+        while offset < t.data.len() {
+            match from_bytes_prefix::<ObMessage>(&t.data[offset..]) {
+                Ok((msg, size)) if msg.is_mutation() && !matches!(msg, ObMessage::Group(_)) => {
+                    messages.push((msg, t.data[offset..offset + size].to_vec()));
+                    offset += size;
                 }
+                _ => return self.close(CloseCode::Error, "group must hold mutations"),
+            }
+        }
+        if messages.len() > MAX_GROUP_MESSAGES {
+            return self.close(CloseCode::Error, "group too large");
+        }
 
-                board.broadcast(t)
-            });
+        let username = self.username();
+        let acting_username = self.acting_username();
+        let (messages, raw): (Vec<ObMessage>, Vec<Vec<u8>>) = messages.into_iter().unzip();
+        if let Some(Err(e)) = self.with_board(|b| b.check_group(&username, &acting_username, &messages)) {
+            warn!("Client {} sent a group which cannot be applied: {}", username, e);
+            return Ok(());
+        }
+
+        /* messages of the group reach the history only once it ends */
+        let board_name = self.board_context.as_ref().unwrap().board_name.clone();
+        let quota_exceeded = SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let owner = server.find(&board_name).map(|b| b.owner.clone()).unwrap_or_default();
+            server.exceeds_storage_quota(&owner, t.data.len())
         });
+        if quota_exceeded {
+            return self.close(CloseCode::Policy, "storage quota exceeded");
+        }
+
+        self.with_board(|b| b.begin_group());
+        let mut result = Ok(());
+        for (msg, raw) in messages.into_iter().zip(raw) {
+            result = self.handle_in_board_msg(msg, &raw);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.with_board(|b| b.end_group());
+        result
+    }
+
+    fn handle_create_view_token(&mut self, t: CreateViewToken) -> Result<(), Error> {
+        let username = self.acting_username();
+        let board_name = self.board_context.as_ref().unwrap().board_name.clone();
+        let token = SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let secret = server.config.invite_secret.clone();
+            server.find(&board_name).map(|b| b.create_view_token(&username, &board_name, t, &secret))
+        });
+
+        match token {
+            Some(Ok(token)) => self.out.send(to_bytes(&ObMessage::ViewToken(ViewTokenMessage { token: token.as_str() })).unwrap()),
+            Some(Err(e)) => {
+                warn!("Client {} cannot create view token: {}", username, e);
+                Ok(())
+            }
+            None => Ok(())
+        }
+    }
+
+    /// Exports run on the job worker which reports the progress directly
+    /// to the requester and finally sends the download url.
+    fn handle_request_export(&mut self) -> Result<(), Error> {
+        let username = self.username();
+        let board_name = self.board_context.as_ref().unwrap().board_name.clone();
+        let (dir, ttl) = match SERVER.with(|x| x.borrow().config.export_dir.clone().map(|d| (d, x.borrow().config.export_ttl))) {
+            Some(t) => t,
+            None => {
+                warn!("Client {} requested an export but exports are disabled", username);
+                return Ok(());
+            }
+        };
+        let (render, votes) = match self.with_board(|b| (b.deferred_snapshot(), b.vote_tally())) {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        let out = self.out.clone();
+        let job_id = SERVER.with(|x| x.borrow_mut().jobs.submit(&format!("export of {}", board_name), move |job_id| {
+            let progress = |percent| out.send(to_bytes(&ObMessage::ExportProgress(ExportProgress { job_id, percent })).unwrap());
+
+            let _ = progress(0);
+            let snapshot = render();
+            let _ = progress(50);
+
+            /* the name must not be guessable as the board may be private */
+            let name = format!("{:08x}-{}.bmp", job_id, to_hex(&rand::random::<[u8; 16]>()));
+            exports::store(&dir, &name, &snapshot).map_err(|e| e.to_string())?;
+            if let Err(e) = exports::sweep(&dir, ttl) {
+                warn!("Cannot remove expired exports: {}", e);
+            }
+            let _ = progress(100);
+
+            let url = format!("/exports/{}", name);
+            out.send(to_bytes(&ObMessage::ExportReady(ExportReady { job_id, url: url.as_str(), votes })).unwrap()).map_err(|e| e.to_string())
+        }));
+
+        info!("Client {} requested export job {}", username, job_id);
+        Ok(())
+    }
+
+    fn handle_set_private(&mut self, t: SetPrivate) -> Result<(), Error> {
+        let username = self.acting_username();
+        if let Some(Err(e)) = self.with_board(|b| b.set_private(&username, t)) {
+            warn!("Client {} cannot change board privacy: {}", username, e);
+        }
+        Ok(())
+    }
+
+    fn handle_set_access(&mut self, t: SetAccess) -> Result<(), Error> {
+        let username = self.acting_username();
+        match self.with_board(|b| b.set_access(&username, t)) {
+            Some(Ok(list)) => self.out.send(list),
+            Some(Err(e)) => {
+                warn!("Client {} cannot change board access: {}", username, e);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn handle_request_access_list(&mut self) -> Result<(), Error> {
+        let username = self.acting_username();
+        match self.with_board(|b| b.access_list(&username)) {
+            Some(Ok(list)) => self.out.send(list),
+            Some(Err(e)) => {
+                warn!("Client {} cannot see board access: {}", username, e);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn handle_admin_broadcast(&mut self, t: AdminBroadcast) -> Result<(), Error> {
+        if !self.is_admin() {
+            return self.close(CloseCode::Policy, "admin only");
+        }
+
+        warn!("Admin {} announces: {}", self.username(), t.message);
+        if let Err(e) = SERVER.with(|x| x.borrow_mut().announce(t.message)) {
+            warn!("Cannot announce: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Deleting the board the admin is in disconnects the admin as well.
+    fn handle_admin_delete_board(&mut self, t: AdminDeleteBoard) -> Result<(), Error> {
+        if !self.is_admin() {
+            return self.close(CloseCode::Policy, "admin only");
+        }
+
+        warn!("Admin {} deletes board {}", self.username(), t.name);
+        if !SERVER.with(|x| x.borrow_mut().delete(t.name)) {
+            warn!("Cannot delete board {}, it does not exist", t.name);
+        }
+        Ok(())
+    }
+
+    fn handle_respond_join(&mut self, t: RespondJoin) -> Result<(), Error> {
+        let username = self.acting_username();
+        match self.with_board(|b| b.respond_join(&username, t)) {
+            Some(Ok(waiting)) => {
+                if waiting.timeout(0, JOIN_RESPONSE).is_err() {
+                    warn!("Cannot notify client waiting for join");
+                }
+            }
+            Some(Err(e)) => warn!("Client {} cannot respond to join request: {}", username, e),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn handle_time_sync(&mut self, t: TimeSync, received: u64) -> Result<(), Error> {
+        self.out.send(to_bytes(&ObMessage::TimeSync(TimeSync {
+            client_send_time: t.client_send_time,
+            server_receive_time: received,
+            server_send_time: clock::now_millis(),
+        })).unwrap())
+    }
+
+    fn request_id(&self) -> RequestId {
+        RequestId { connection: self.connection_id, message: self.message_count }
+    }
+
+    /// Closes the connection with the request id attached to the reason so
+    /// that reported failures can be found in the logs. The English reason
+    /// is kept first as a stable machine readable code, followed by its
+    /// translation when the client prefers another language.
+    fn close(&self, code: CloseCode, reason: &'static str) -> Result<(), Error> {
+        warn!("Closing connection {}: {}", self.out.peer(), reason);
+        match self.locale.translate(reason) {
+            text if text == reason => self.out.close(code, format!("{} ({})", reason, self.request_id())),
+            text => self.out.close(code, format!("{}: {} ({})", reason, text, self.request_id())),
+        }
+    }
+
+    fn redirect(&self, target: Option<String>) -> Result<(), Error> {
+        let url = target.unwrap_or_default();
+        self.out.send(to_bytes(&ObMessage::Reconnect(Reconnect { url: url.as_str() })).unwrap())?;
+        self.close(CloseCode::Again, "server is draining")
+    }
+
+    fn quarantine(&self, frame: &[u8], reason: &str) {
+        let dir = match SERVER.with(|x| x.borrow().config.quarantine_dir.clone()) {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        let id = self.request_id();
+        let username = self.username();
+        let board_name = self.board_context.as_ref().map(|x| x.board_name.clone());
+        let frame = frame.to_vec();
+        let reason = reason.to_string();
+
+        warn!("Quarantining frame: {}", reason);
+        SERVER.with(|x| x.borrow_mut().jobs.submit(&format!("quarantine {}", id), move |_| {
+            quarantine::capture(&dir, id, &username, board_name.as_ref().map(|x| x.as_str()), &frame, &reason).map_err(|e| e.to_string())
+        }));
+    }
+
+    fn record(&mut self, timestamp: u64, frame: Vec<u8>) {
+        let record = Record { timestamp, connection: self.connection_id, frame };
+        let board_name = match &self.board_context {
+            Some(t) => &t.board_name,
+            None => {
+                if self.preamble.len() < MAX_PREAMBLE {
+                    self.preamble.push(record);
+                }
+                return;
+            }
+        };
+
+        let path = SERVER.with(|x| {
+            let config = &x.borrow().config;
+            match config.record_boards.iter().any(|x| x == board_name) {
+                true => Some(config.record_dir.join(format!("{}.cap", board_name))),
+                false => None,
+            }
+        });
+
+        let path = match path {
+            Some(t) => t,
+            None => return self.preamble.clear(),
+        };
+
+        let preamble = std::mem::take(&mut self.preamble);
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| preamble.iter().chain(Some(&record)).map(|x| capture::append(&path, x)).collect::<std::io::Result<()>>());
+        if let Err(e) = result {
+            warn!("Cannot record frame: {}", e);
+        }
+    }
+
+    fn username(&self) -> String {
+        self.authenticated_user.as_ref().map(|x| x.username.clone()).unwrap_or_default()
+    }
+
+    fn display_name(&self) -> String {
+        self.authenticated_user.as_ref().map(|x| x.display_name.clone()).unwrap_or_default()
+    }
+
+    /// Username for ownership checks, admins act as the owner of the board.
+    fn acting_username(&self) -> String {
+        match self.authenticated_user.as_ref().map(|x| x.admin).unwrap_or(false) {
+            true => self.with_board(|b| b.owner.clone()).unwrap_or_else(|| self.username()),
+            false => self.username(),
+        }
+    }
+
+    fn is_admin(&self) -> bool {
+        self.authenticated_user.as_ref().map(|x| x.admin).unwrap_or(false)
+    }
+
+    fn with_board<F, R>(&self, f: F) -> Option<R> where F: FnOnce(&mut Board) -> R {
+        SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let board_name = &self.board_context.as_ref()?.board_name;
+            server.find(board_name).map(f)
+        })
+    }
+
+    fn broadcast_to_board(&mut self, t: &Vec<u8>, kind: NotificationFlags, reliability: Reliability) -> Result<(), Error> {
+        let board_name = match &self.board_context {
+            Some(x) => x.board_name.clone(),
+            None => return Ok(()),
+        };
+
+        /* boards are deleted by retention or admins while clients are still in them */
+        let owner = match SERVER.with(|x| x.borrow_mut().find(&board_name).map(|b| b.owner.clone())) {
+            Some(t) => t,
+            None => {
+                self.board_context = None;
+                return self.close(CloseCode::Away, "board deleted");
+            }
+        };
+
+        let quota_exceeded = SERVER.with(|x| {
+            let mut server = x.borrow_mut();
+            let stores = server.find(&board_name).map(|b| b.stores(reliability)).unwrap_or(false);
+            if stores && server.exceeds_storage_quota(&owner, t.len()) {
+                return true;
+            }
+
+            if let Some(board) = server.find(&board_name) {
+                board.publish_with(t, kind, reliability);
+            }
+            false
+        });
+
+        if quota_exceeded {
+            return self.close(CloseCode::Policy, "storage quota exceeded");
+        }
         Ok(())
     }
 }
@@ -0,0 +1,222 @@
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::path::PathBuf;
+use rsa::RsaPublicKey;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+
+/// Server configuration read from `OB2_*` environment variables.
+pub struct Config {
+    /// Address of the WebSocket and HTTP listener. IPv6 wildcard `[::]`
+    /// accepts IPv4 connections too on dual-stack hosts.
+    pub listen: String,
+    pub max_storage_bytes: usize,
+    /// Users not bound by quotas.
+    pub quota_exempt: Vec<String>,
+    /// User ids of verified tokens acting as the owner of every board and
+    /// allowed to send admin messages.
+    pub admins: Vec<String>,
+    /// Secret signing invite tokens. Random when not configured, in which
+    /// case invites do not survive a restart.
+    pub invite_secret: Vec<u8>,
+    /// Origins allowed to read the HTTP endpoints from a browser, `*`
+    /// allows any origin.
+    pub cors_origins: Vec<String>,
+    /// Bearer token of the admin HTTP endpoints, disabled when not set.
+    pub admin_token: Option<String>,
+    /// Bearer token of admin endpoints allowing reads only.
+    pub admin_read_token: Option<String>,
+    /// Secret for HMAC signatures of admin requests, signatures are not
+    /// required when not set.
+    pub admin_signing_secret: Option<Vec<u8>>,
+    /// Directory where frames failing to decode or crashing a handler are
+    /// stored, disabled when not set.
+    pub quarantine_dir: Option<PathBuf>,
+    /// Boards whose inbound frames are recorded into capture files.
+    pub record_boards: Vec<String>,
+    pub record_dir: PathBuf,
+    /// Boards without activity for this many days are deleted, zero keeps
+    /// boards forever.
+    pub retention_days: u64,
+    /// Directory where finished exports are stored, exports are disabled
+    /// when not set.
+    pub export_dir: Option<PathBuf>,
+    /// Seconds for which finished exports can be downloaded.
+    pub export_ttl: u64,
+    /// Oldest client version allowed to connect, any client is allowed
+    /// when not set.
+    pub min_client_version: Option<String>,
+    /// Directory with Excalidraw and tldraw scenes which admins can import
+    /// into boards, imports are disabled when not set.
+    pub import_dir: Option<PathBuf>,
+    /// Directory with scenes of board templates named by template id.
+    pub template_dir: Option<PathBuf>,
+    /// URL receiving a JSON POST for every board created by a schedule.
+    pub schedule_webhook: Option<String>,
+    /// Partial feature rollouts as `feature=percent`.
+    pub features: Vec<String>,
+    /// Secret of HS256 signed user tokens.
+    pub jwt_secret: Option<Vec<u8>>,
+    /// Public key of RS256 signed user tokens, read from a PEM file.
+    pub jwt_public_key: Option<RsaPublicKey>,
+    /// Key set of an identity provider with RS256 signing keys, which are
+    /// used instead of `jwt_public_key` for tokens naming their key.
+    pub jwks_url: Option<String>,
+    /// Seconds between refetches of the key set.
+    pub jwks_refresh: u64,
+    /// Without any key configured tokens are taken as usernames without
+    /// verification. Only meant for development, otherwise nobody but
+    /// guests can authenticate without a key.
+    pub insecure_dev_auth: bool,
+    /// Clients authenticating with an empty token become guests with
+    /// a generated username, guests cannot create boards.
+    pub guest_access: bool,
+    /// URL of the profile service with a `{user_id}` placeholder, user
+    /// profiles are not resolved when not set.
+    pub profile_url: Option<String>,
+    /// Seconds for which resolved profiles are cached.
+    pub profile_ttl: u64,
+    /// Avatars of resolved profiles are served by this server instead of
+    /// their original host.
+    pub avatar_proxy: bool,
+    /// Larger avatars are not proxied.
+    pub avatar_max_bytes: usize,
+    /// Seconds for which avatars are cached, by this server and by clients.
+    pub avatar_ttl: u64,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            listen: var("OB2_LISTEN", "0.0.0.0:3013".to_string()),
+            max_storage_bytes: var("OB2_MAX_STORAGE_BYTES", 64 * 1024 * 1024),
+            quota_exempt: list("OB2_QUOTA_EXEMPT"),
+            admins: list("OB2_ADMINS"),
+            invite_secret: env::var("OB2_INVITE_SECRET")
+                .map(|x| x.into_bytes())
+                .unwrap_or_else(|_| rand::random::<[u8; 32]>().to_vec()),
+            cors_origins: list("OB2_CORS_ORIGINS"),
+            admin_token: env::var("OB2_ADMIN_TOKEN").ok().filter(|x| !x.is_empty()),
+            admin_read_token: env::var("OB2_ADMIN_READ_TOKEN").ok().filter(|x| !x.is_empty()),
+            admin_signing_secret: env::var("OB2_ADMIN_SIGNING_SECRET").ok().filter(|x| !x.is_empty()).map(|x| x.into_bytes()),
+            quarantine_dir: env::var("OB2_QUARANTINE_DIR").ok().map(PathBuf::from),
+            record_boards: list("OB2_RECORD_BOARDS"),
+            record_dir: PathBuf::from(var("OB2_RECORD_DIR", "captures".to_string())),
+            retention_days: var("OB2_RETENTION_DAYS", 0),
+            export_dir: env::var("OB2_EXPORT_DIR").ok().map(PathBuf::from),
+            export_ttl: var("OB2_EXPORT_TTL", 60 * 60),
+            min_client_version: env::var("OB2_MIN_CLIENT_VERSION").ok().filter(|x| !x.is_empty()),
+            import_dir: env::var("OB2_IMPORT_DIR").ok().map(PathBuf::from),
+            template_dir: env::var("OB2_TEMPLATE_DIR").ok().map(PathBuf::from),
+            schedule_webhook: env::var("OB2_SCHEDULE_WEBHOOK").ok().filter(|x| !x.is_empty()),
+            features: list("OB2_FEATURES"),
+            jwt_secret: env::var("OB2_JWT_SECRET").ok().filter(|x| !x.is_empty()).map(|x| x.into_bytes()),
+            jwt_public_key: public_key("OB2_JWT_PUBLIC_KEY_FILE"),
+            jwks_url: env::var("OB2_JWKS_URL").ok().filter(|x| !x.is_empty()),
+            jwks_refresh: var("OB2_JWKS_REFRESH", 60 * 60),
+            insecure_dev_auth: var("OB2_INSECURE_DEV_AUTH", false),
+            guest_access: var("OB2_GUEST_ACCESS", false),
+            profile_url: env::var("OB2_PROFILE_URL").ok().filter(|x| !x.is_empty()),
+            profile_ttl: var("OB2_PROFILE_TTL", 10 * 60),
+            avatar_proxy: var("OB2_AVATAR_PROXY", false),
+            avatar_max_bytes: var("OB2_AVATAR_MAX_BYTES", 256 * 1024),
+            avatar_ttl: var("OB2_AVATAR_TTL", 24 * 60 * 60),
+        }
+    }
+
+    /// Whether user tokens can be verified at all.
+    pub fn has_token_key(&self) -> bool {
+        self.jwt_secret.is_some() || self.jwt_public_key.is_some() || self.jwks_url.is_some()
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.cors_origins.iter().any(|x| x == "*" || x == origin)
+    }
+
+    /// Versions are compared by dot separated numeric components, missing
+    /// trailing components count as zero so `1.2` equals `1.2.0`. Clients
+    /// not reporting any version or reporting one which is not numeric are
+    /// older than every minimum.
+    pub fn supports_client(&self, version: Option<&str>) -> bool {
+        let components = |x: &str| -> Option<Vec<u32>> {
+            x.split('.').map(|x| x.trim().parse().ok()).collect()
+        };
+
+        let minimum = match &self.min_client_version {
+            None => return true,
+            Some(minimum) => minimum,
+        };
+        match (version.and_then(components), components(minimum)) {
+            (Some(mut version), Some(mut minimum)) => {
+                let len = version.len().max(minimum.len());
+                version.resize(len, 0);
+                minimum.resize(len, 0);
+                version >= minimum
+            }
+            _ => false,
+        }
+    }
+}
+
+fn var<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|x| x.parse().ok()).unwrap_or(default)
+}
+
+fn list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|x| x.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Misconfigured keys stop the server instead of silently disabling
+/// verification.
+fn public_key(name: &str) -> Option<RsaPublicKey> {
+    let path = env::var(name).ok().filter(|x| !x.is_empty())?;
+    let pem = fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {} {}: {}", name, path, e));
+    let key = RsaPublicKey::from_public_key_pem(&pem)
+        .or_else(|_| RsaPublicKey::from_pkcs1_pem(&pem))
+        .unwrap_or_else(|_| panic!("{} {} is not an RSA public key", name, path));
+    Some(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    fn config(minimum: &str) -> Config {
+        let mut config = Config::from_env();
+        config.min_client_version = Some(minimum.to_string());
+        config
+    }
+
+    #[test]
+    fn test_supports_client() {
+        let config = config("1.2.0");
+        assert!(config.supports_client(Some("1.2.0")));
+        assert!(config.supports_client(Some("1.10")));
+        assert!(config.supports_client(Some("2")));
+        assert!(!config.supports_client(Some("1.1.9")));
+        assert!(!config.supports_client(None));
+
+        let mut config = config;
+        config.min_client_version = None;
+        assert!(config.supports_client(None));
+        assert!(config.supports_client(Some("beta")));
+    }
+
+    #[test]
+    fn test_supports_client_missing_components() {
+        assert!(config("1.2.0").supports_client(Some("1.2")));
+        assert!(config("1.2").supports_client(Some("1.2.0")));
+        assert!(!config("1.2.1").supports_client(Some("1.2")));
+    }
+
+    #[test]
+    fn test_supports_client_not_numeric() {
+        assert!(!config("1.0").supports_client(Some("1.x")));
+        assert!(!config("1.0").supports_client(Some("beta")));
+        assert!(!config("1.0").supports_client(Some("")));
+        assert!(!config("1.x").supports_client(Some("9.9")));
+    }
+}
@@ -17,12 +17,26 @@ pub fn from_bytes<'a, T>(s: &'a [u8]) -> Result<T> where T: Deserialize<'a>,
 {
     let mut deserializer = Deserializer::from_bytes(s);
     let t = T::deserialize(&mut deserializer)?;
+
+    /* trailing bytes would give a value more than one representation */
+    if deserializer.pos != s.len() {
+        return Err(Error::Message("Trailing bytes after value".to_string()));
+    }
     Ok(t)
 }
 
+/// Deserializes a value from the start of the input and returns it along
+/// with the number of bytes it occupied.
+pub fn from_bytes_prefix<'a, T>(s: &'a [u8]) -> Result<(T, usize)> where T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_bytes(s);
+    let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.pos))
+}
+
 impl<'de> Deserializer<'de> {
     fn read_u8(&mut self) -> Result<u8> {
-        let val = self.input[self.pos];
+        let val = *self.input.get(self.pos).ok_or_else(unexpected_end)?;
         self.pos += 1;
         Ok(val)
     }
@@ -57,7 +71,7 @@ impl<'de> Deserializer<'de> {
     }
 
     fn read_bytes(&mut self, length: usize) -> Result<&'de [u8]> {
-        let ptr = &self.input[self.pos..self.pos + length];
+        let ptr = self.input.get(self.pos..self.pos + length).ok_or_else(unexpected_end)?;
         self.pos += length;
         Ok(ptr)
     }
@@ -71,6 +85,10 @@ impl<'de> Deserializer<'de> {
     }
 }
 
+fn unexpected_end() -> Error {
+    Error::Message("Unexpected end of input".to_string())
+}
+
 impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     type Error = Error;
 
@@ -0,0 +1,46 @@
+/// Plan tier of the user as issued in the token claims.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Plan {
+    Free,
+    Pro,
+    Enterprise,
+}
+
+/// Limits granted by a plan. Board-wide limits are taken from the plan
+/// of the board owner.
+pub struct Entitlements {
+    pub max_boards: usize,
+    pub max_members: usize,
+    pub history_size: u16,
+}
+
+impl Plan {
+    /// Unknown plans are treated as the free one.
+    pub fn from_name(name: Option<&str>) -> Plan {
+        match name {
+            Some("pro") => Plan::Pro,
+            Some("enterprise") => Plan::Enterprise,
+            _ => Plan::Free,
+        }
+    }
+
+    pub fn entitlements(self) -> Entitlements {
+        match self {
+            Plan::Free => Entitlements {
+                max_boards: 3,
+                max_members: 10,
+                history_size: 4096,
+            },
+            Plan::Pro => Entitlements {
+                max_boards: 50,
+                max_members: 50,
+                history_size: std::u16::MAX,
+            },
+            Plan::Enterprise => Entitlements {
+                max_boards: std::usize::MAX,
+                max_members: 250,
+                history_size: std::u16::MAX,
+            },
+        }
+    }
+}
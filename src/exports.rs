@@ -0,0 +1,49 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Stores the exported artifact into the export directory under `name`.
+pub fn store(dir: &Path, name: &str, data: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(name), data)
+}
+
+/// Loads the artifact unless it is older than `ttl` seconds.
+pub fn load(dir: &Path, name: &str, ttl: u64) -> io::Result<Vec<u8>> {
+    if !is_valid_name(name) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid artifact name"));
+    }
+
+    let path = dir.join(name);
+    if is_expired(&path, ttl)? {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "artifact expired"));
+    }
+    fs::read(path)
+}
+
+/// Removes artifacts older than `ttl` seconds.
+pub fn sweep(dir: &Path, ttl: u64) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_expired(&path, ttl)? {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Artifact names are generated by the server, anything else must not
+/// reach the filesystem.
+fn is_valid_name(name: &str) -> bool {
+    match name.strip_suffix(".bmp") {
+        Some(stem) => !stem.is_empty() && stem.chars().all(|x| x.is_ascii_hexdigit() || x == '-'),
+        None => false,
+    }
+}
+
+fn is_expired(path: &Path, ttl: u64) -> io::Result<bool> {
+    let modified = fs::metadata(path)?.modified()?;
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+    Ok(age > Duration::from_secs(ttl))
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::RwLock;
+use serde::Serialize;
+
+/// Rollouts differing from the defaults, changed through configuration
+/// and the admin API.
+static ROLLOUTS: RwLock<Option<HashMap<Feature, Rollout>>> = RwLock::new(None);
+
+/// Server behaviour which can be rolled out gradually.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Full history transmissions of joining clients are rate limited.
+    JoinThrottle,
+    /// Provisional messages retried after a resume are dropped.
+    DuplicateDetection,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 2] = [Feature::JoinThrottle, Feature::DuplicateDetection];
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        match name {
+            "join_throttle" => Some(Feature::JoinThrottle),
+            "duplicate_detection" => Some(Feature::DuplicateDetection),
+            _ => None,
+        }
+    }
+}
+
+/// Feature is enabled for `percent` of tenants picked by hash of the
+/// tenant name, and always for the listed tenants.
+#[derive(Clone, Debug, Serialize)]
+pub struct Rollout {
+    pub percent: u8,
+    pub tenants: Vec<String>,
+}
+
+impl Rollout {
+    fn includes(&self, feature: Feature, tenant: &str) -> bool {
+        if self.tenants.iter().any(|x| x == tenant) {
+            return true;
+        }
+
+        /* stable hash so a tenant stays in or out while the rollout grows */
+        let mut hasher = DefaultHasher::new();
+        (feature, tenant).hash(&mut hasher);
+        hasher.finish() % 100 < self.percent as u64
+    }
+}
+
+#[derive(Serialize)]
+pub struct FeatureStatus {
+    pub feature: Feature,
+    pub rollout: Rollout,
+}
+
+/// Whether the feature is enabled for the tenant, the board owner.
+pub fn enabled(feature: Feature, tenant: &str) -> bool {
+    match ROLLOUTS.read().unwrap().as_ref().and_then(|x| x.get(&feature)) {
+        Some(rollout) => rollout.includes(feature, tenant),
+        None => true,
+    }
+}
+
+pub fn set(feature: Feature, rollout: Option<Rollout>) {
+    let mut rollouts = ROLLOUTS.write().unwrap();
+    let rollouts = rollouts.get_or_insert_with(HashMap::new);
+    match rollout {
+        Some(rollout) => rollouts.insert(feature, rollout),
+        None => rollouts.remove(&feature),
+    };
+}
+
+/// Applies rollouts given as `feature=percent`, features are fully
+/// enabled by default.
+pub fn configure(rollouts: &[String]) {
+    for x in rollouts {
+        let mut pair = x.splitn(2, '=');
+        match (pair.next().and_then(Feature::from_name), pair.next().and_then(|x| x.parse::<u8>().ok())) {
+            (Some(feature), Some(percent)) => set(feature, Some(Rollout { percent: percent.min(100), tenants: vec![] })),
+            _ => log::warn!("Ignoring invalid feature rollout {}", x),
+        }
+    }
+}
+
+pub fn statuses() -> Vec<FeatureStatus> {
+    let rollouts = ROLLOUTS.read().unwrap();
+    Feature::ALL.iter().map(|x| FeatureStatus {
+        feature: *x,
+        rollout: rollouts.as_ref().and_then(|r| r.get(x)).cloned().unwrap_or(Rollout { percent: 100, tenants: vec![] }),
+    }).collect()
+}
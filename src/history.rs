@@ -0,0 +1,47 @@
+//! Board history as JSON Lines. Every line describes one message:
+//!
+//! ```text
+//! {"index":0,"offset":0,"size":14,"message":{"Draw":{...}},"raw":"05..."}
+//! ```
+//!
+//! `index` is the position of the message in the history, `offset` and
+//! `size` locate its bytes. `message` is the decoded message meant for
+//! analysis, `raw` holds the exact encoded message in hex and is what the
+//! `history-import` tool sends to reconstruct the board.
+
+use serde::Serialize;
+use crate::de::from_bytes_prefix;
+use crate::error::Error;
+use crate::invite::to_hex;
+use crate::messages::Message;
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    index: usize,
+    offset: usize,
+    size: usize,
+    message: Message<'a>,
+    raw: String,
+}
+
+pub fn to_jsonl(history: &[u8]) -> Result<String, Error> {
+    let mut jsonl = String::new();
+    let mut offset = 0;
+    let mut index = 0;
+    while offset < history.len() {
+        let (message, size) = from_bytes_prefix::<Message>(&history[offset..])?;
+        let entry = Entry {
+            index,
+            offset,
+            size,
+            message,
+            raw: to_hex(&history[offset..offset + size]),
+        };
+        jsonl.push_str(&serde_json::to_string(&entry).map_err(|e| Error::Message(e.to_string()))?);
+        jsonl.push('\n');
+
+        offset += size;
+        index += 1;
+    }
+    Ok(jsonl)
+}
@@ -0,0 +1,59 @@
+//! Periodic server-wide tasks: deleting boards after their retention
+//! period and creating boards of schedules. The event loop only has
+//! timeouts of connections, so the server connects to itself once and the
+//! timer runs on that connection however many clients are connected.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use ws::{Factory, Sender};
+use ws::util::Token;
+use url::Url;
+use crate::client::Client;
+use crate::server::Server;
+
+pub const INTERVAL_MS: u64 = 30 * 1000;
+pub const TICK: Token = Token(7);
+
+/// Incoming connections are clients, the only outgoing one is the
+/// connection of the housekeeping timer.
+pub struct Connections;
+
+impl Factory for Connections {
+    type Handler = Client;
+
+    fn connection_made(&mut self, out: Sender) -> Client {
+        Client::new(Arc::new(out))
+    }
+
+    fn client_connected(&mut self, out: Sender) -> Client {
+        Client::housekeeper(Arc::new(out))
+    }
+}
+
+pub fn run(server: &mut Server) {
+    server.sweep();
+    server.run_schedules();
+}
+
+/// Address of the listener reachable from this host, wildcard addresses
+/// are replaced by loopback.
+pub fn loopback_url(address: SocketAddr) -> Url {
+    let ip = match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    Url::parse(&format!("ws://{}/", SocketAddr::new(ip, address.port()))).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::loopback_url;
+
+    #[test]
+    fn test_loopback_url() {
+        assert_eq!(loopback_url("0.0.0.0:3013".parse().unwrap()).as_str(), "ws://127.0.0.1:3013/");
+        assert_eq!(loopback_url("[::]:3013".parse().unwrap()).as_str(), "ws://[::1]:3013/");
+        assert_eq!(loopback_url("10.0.0.1:8080".parse().unwrap()).as_str(), "ws://10.0.0.1:8080/");
+    }
+}
@@ -0,0 +1,635 @@
+use ws::{Request, Response};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::server::{Server, Board};
+use crate::canvas::{CANVAS_WIDTH, CANVAS_HEIGHT};
+use crate::quarantine;
+use crate::exports;
+use crate::logging;
+use crate::history;
+use crate::import;
+use crate::features::{self, Feature, Rollout};
+use crate::server::ADMIN_REPLAY_WINDOW;
+use crate::invite::from_hex;
+use crate::clock;
+use crate::schedules::Schedule;
+use crate::workspaces::Workspace;
+use crate::messages::{LockState, Auth, ObjectId, Position, VoteTally};
+use crate::auth::auth;
+use hmac::{Hmac, Mac};
+use log::warn;
+use sha2::Sha256;
+
+/// OpenAPI document describing the HTTP endpoints. Keep in sync with the
+/// routes below.
+const OPENAPI: &[u8] = include_bytes!("openapi.json");
+
+/// Retry hint of board locks when the admin does not give one.
+const DEFAULT_RETRY_AFTER: u16 = 30;
+
+/// Board metadata. Private boards are served only to admins.
+#[derive(Serialize)]
+struct BoardMetadata<'a> {
+    name: &'a str,
+    owner: &'a str,
+    private: bool,
+    members: usize,
+    width: u32,
+    height: u32,
+    version: u32,
+    /// Unix time when the board is deleted unless there is some activity.
+    expires_at: Option<u64>,
+    bookmarks: Vec<Bookmark<'a>>,
+    votes: Vec<VoteTally>,
+    /// Connected clients per client version, listed to admins only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_versions: Option<BTreeMap<String, usize>>,
+    /// Workspace of the board, listed to admins only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<&'a str>,
+}
+
+/// Zoom is in percent.
+#[derive(Serialize)]
+struct Bookmark<'a> {
+    object_id: ObjectId,
+    label: &'a str,
+    position: Position,
+    zoom: u16,
+}
+
+/// Content found on one of the boards of the user.
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    board: &'a str,
+    object_id: Option<ObjectId>,
+    position: Position,
+}
+
+/// Handles plain HTTP requests arriving at the WebSocket port. Returns
+/// `None` for WebSocket upgrade requests which proceed with the handshake.
+pub fn handle(server: &mut Server, req: &Request) -> Option<Response> {
+    if req.header("upgrade").is_some() {
+        return None;
+    }
+
+    /* requests from browsers of other origins must be allowed explicitly */
+    let origin = req.header("origin").map(|x| String::from_utf8_lossy(x).into_owned());
+    if let Some(ref origin) = origin {
+        if !server.config.allows_origin(origin) {
+            return Some(Response::new(403, "Forbidden", vec![]));
+        }
+    }
+
+    let mut response = match req.method() {
+        "OPTIONS" => preflight(),
+        _ => route(server, req),
+    };
+
+    if let Some(origin) = origin {
+        response.headers_mut().push(("Access-Control-Allow-Origin".into(), origin.into_bytes()));
+        response.headers_mut().push(("Access-Control-Expose-Headers".into(), b"ETag".to_vec()));
+        response.headers_mut().push(("Vary".into(), b"Origin".to_vec()));
+    }
+    Some(response)
+}
+
+fn preflight() -> Response {
+    let mut response = Response::new(204, "No Content", vec![]);
+    response.headers_mut().push(("Access-Control-Allow-Methods".into(), b"GET, PUT, DELETE, OPTIONS".to_vec()));
+    response.headers_mut().push(("Access-Control-Allow-Headers".into(), b"Authorization, If-None-Match, X-Ob2-Timestamp, X-Ob2-Signature".to_vec()));
+    response.headers_mut().push(("Access-Control-Max-Age".into(), b"86400".to_vec()));
+    response
+}
+
+fn route(server: &mut Server, req: &Request) -> Response {
+    let path = req.resource().splitn(2, '?').next().unwrap_or("");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    if segments == ["openapi.json"] {
+        let mut response = Response::new(200, "OK", OPENAPI.to_vec());
+        response.headers_mut().push(("Content-Type".into(), b"application/json".to_vec()));
+        return response;
+    }
+
+    if segments[0] == "admin" {
+        return admin(server, req, &segments[1..]);
+    }
+
+    if req.method() != "GET" {
+        return Response::new(405, "Method Not Allowed", vec![]);
+    }
+
+    if let ["exports", name] = segments[..] {
+        return export(server, name);
+    }
+
+    if segments == ["search"] {
+        return search(server, req);
+    }
+
+    if let ["avatars", user_id] = segments[..] {
+        return avatar(server, user_id);
+    }
+
+    let name = match segments.get(1).and_then(|x| decode(x)) {
+        Some(name) if segments[0] == "boards" => name,
+        _ => return not_found(),
+    };
+
+    let retention_days = server.config.retention_days;
+    let board = match server.find(&name) {
+        Some(board) if !board.is_restricted() => board,
+        _ => return not_found(),
+    };
+
+    /* snapshot only changes with the canvas so its version is a good etag */
+    let etag = format!("\"{}\"", board.canvas_version());
+    let mut response = match &segments[2..] {
+        [] => json(&metadata(&name, board, retention_days)),
+        ["snapshot.bmp"] if req.header("if-none-match").map(|x| x.as_slice()) == Some(etag.as_bytes()) => {
+            let mut response = Response::new(304, "Not Modified", vec![]);
+            response.headers_mut().push(("ETag".into(), etag.into_bytes()));
+            response
+        }
+        ["snapshot.bmp"] => {
+            let mut response = Response::new(200, "OK", board.render_snapshot().to_vec());
+            response.headers_mut().push(("Content-Type".into(), b"image/bmp".to_vec()));
+            response.headers_mut().push(("ETag".into(), etag.into_bytes()));
+            response
+        }
+        _ => return not_found(),
+    };
+
+    response.headers_mut().push(("Cache-Control".into(), b"no-cache".to_vec()));
+    response
+}
+
+/// Permission tier of an admin credential. Auditors can only read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+enum AdminRole {
+    Auditor,
+    Admin,
+}
+
+/// Admin endpoints require one of the configured bearer tokens and do not
+/// exist when no token is configured.
+fn admin(server: &mut Server, req: &Request, segments: &[&str]) -> Response {
+    if server.config.admin_token.is_none() && server.config.admin_read_token.is_none() {
+        return not_found();
+    }
+
+    let token = req.header("authorization").and_then(|x| x.strip_prefix(b"Bearer "));
+    let matches = |expected: &Option<String>| match (token, expected) {
+        (Some(token), Some(expected)) => constant_time_eq(token, expected.as_bytes()),
+        _ => false,
+    };
+    let role = if matches(&server.config.admin_token) {
+        AdminRole::Admin
+    } else if matches(&server.config.admin_read_token) {
+        AdminRole::Auditor
+    } else {
+        return Response::new(401, "Unauthorized", vec![]);
+    };
+
+    if !verify_signature(server, req) {
+        return Response::new(401, "Unauthorized", vec![]);
+    }
+
+    /* every route declares the role it requires */
+    let required = match (req.method(), segments) {
+        ("GET", ["boards"]) | ("GET", ["boards", _, "latency"]) | ("GET", ["boards", _, "history.jsonl"]) | ("GET", ["jobs"]) | ("GET", ["log"]) | ("GET", ["features"]) | ("GET", ["quarantine"]) | ("GET", ["quarantine", _]) | ("GET", ["schedules"]) | ("GET", ["workspaces"]) => AdminRole::Auditor,
+        ("DELETE", ["boards", _]) | ("PUT", ["boards", _, "lock"]) | ("DELETE", ["boards", _, "lock"]) |
+        ("PUT", ["boards", _, "trace"]) | ("PUT", ["boards", _, "import"]) | ("DELETE", ["boards", _, "trace"]) |
+        ("PUT", ["boards", _, "workspace"]) | ("DELETE", ["boards", _, "workspace"]) | ("PUT", ["workspaces", _]) | ("DELETE", ["workspaces", _]) |
+        ("PUT", ["drain"]) | ("DELETE", ["drain"]) | ("PUT", ["log"]) |
+        ("PUT", ["features", _]) | ("DELETE", ["features", _]) | ("PUT", ["schedules", _]) | ("DELETE", ["schedules", _]) => AdminRole::Admin,
+        (_, ["boards"]) | (_, ["boards", _]) | (_, ["boards", _, "lock"]) | (_, ["boards", _, "latency"]) | (_, ["boards", _, "trace"]) | (_, ["boards", _, "history.jsonl"]) | (_, ["boards", _, "import"]) | (_, ["drain"]) | (_, ["jobs"]) | (_, ["log"]) | (_, ["features"]) | (_, ["features", _]) | (_, ["quarantine"]) | (_, ["quarantine", _]) | (_, ["schedules"]) | (_, ["schedules", _]) | (_, ["boards", _, "workspace"]) | (_, ["workspaces"]) | (_, ["workspaces", _]) => {
+            return Response::new(405, "Method Not Allowed", vec![]);
+        }
+        _ => return not_found(),
+    };
+    if role < required {
+        return Response::new(403, "Forbidden", vec![]);
+    }
+
+    match segments {
+        ["boards"] => {
+            let retention_days = server.config.retention_days;
+            let workspace = query(req, "workspace");
+            let boards: Vec<BoardMetadata> = server.boards()
+                .filter(|(_, b)| workspace.is_none() || b.workspace == workspace)
+                .map(|(name, b)| BoardMetadata {
+                    client_versions: Some(b.client_versions()),
+                    workspace: b.workspace.as_ref().map(|x| x.as_str()),
+                    ..metadata(name, b, retention_days)
+                }).collect();
+            json(&boards)
+        }
+        ["jobs"] => json(&server.jobs.statuses()),
+        ["log"] => match req.method() {
+            "PUT" => match query(req, "filter") {
+                Some(filter) => {
+                    logging::set_filter(&filter);
+                    warn!("Log filter changed to {}", filter);
+                    Response::new(204, "No Content", vec![])
+                }
+                None => Response::new(400, "Bad Request", vec![]),
+            },
+            _ => {
+                let mut response = Response::new(200, "OK", logging::filter().into_bytes());
+                response.headers_mut().push(("Content-Type".into(), b"text/plain; charset=utf-8".to_vec()));
+                response
+            }
+        },
+        ["features"] => json(&features::statuses()),
+        ["features", name] => {
+            let feature = match Feature::from_name(name) {
+                Some(t) => t,
+                None => return not_found(),
+            };
+            let rollout = match req.method() {
+                "DELETE" => None,
+                _ => match query(req, "percent").and_then(|x| x.parse::<u8>().ok()) {
+                    Some(percent) if percent <= 100 => Some(Rollout {
+                        percent,
+                        tenants: query(req, "tenants").map(|x| x.split(',').filter(|x| !x.is_empty()).map(|x| x.to_string()).collect()).unwrap_or_default(),
+                    }),
+                    _ => return Response::new(400, "Bad Request", vec![]),
+                },
+            };
+
+            warn!("Rollout of {} changed to {:?}", name, rollout);
+            features::set(feature, rollout);
+            Response::new(204, "No Content", vec![])
+        }
+        ["schedules"] => json(&server.schedules),
+        ["schedules", id] => {
+            let id = match decode(id) {
+                Some(t) => t,
+                None => return Response::new(400, "Bad Request", vec![]),
+            };
+            if req.method() == "DELETE" {
+                return match server.schedules.remove(&id) {
+                    Some(_) => Response::new(204, "No Content", vec![]),
+                    None => not_found(),
+                };
+            }
+
+            let template_id = query(req, "template").and_then(|x| x.parse::<u64>().ok()).unwrap_or(0);
+            let plan = query(req, "plan").unwrap_or_else(|| "free".to_string());
+            let schedule = match (query(req, "cron"), query(req, "name"), query(req, "owner")) {
+                (Some(cron), Some(name), Some(owner)) if !name.is_empty() && !owner.is_empty() => Schedule::new(&cron, template_id, &name, &owner, &plan),
+                _ => return Response::new(400, "Bad Request", vec![]),
+            };
+            match schedule {
+                Ok(schedule) => {
+                    warn!("Schedule {} changed to {:?}", id, schedule);
+                    server.schedules.insert(id, schedule);
+                    Response::new(204, "No Content", vec![])
+                }
+                Err(e) => {
+                    warn!("Invalid schedule {}: {}", id, e);
+                    Response::new(400, "Bad Request", vec![])
+                }
+            }
+        }
+        ["workspaces"] => json(&server.workspaces),
+        ["workspaces", name] => {
+            let name = match decode(name) {
+                Some(t) => t,
+                None => return Response::new(400, "Bad Request", vec![]),
+            };
+            if req.method() == "DELETE" {
+                return match server.delete_workspace(&name) {
+                    true => Response::new(204, "No Content", vec![]),
+                    false => not_found(),
+                };
+            }
+
+            let role = query(req, "role").unwrap_or_else(|| "editor".to_string());
+            match Workspace::new(&query(req, "members").unwrap_or_default(), &role) {
+                Ok(workspace) => {
+                    warn!("Workspace {} changed to {:?}", name, workspace);
+                    server.workspaces.insert(name, workspace);
+                    Response::new(204, "No Content", vec![])
+                }
+                Err(e) => {
+                    warn!("Invalid workspace {}: {}", name, e);
+                    Response::new(400, "Bad Request", vec![])
+                }
+            }
+        }
+        ["boards", name, "workspace"] => {
+            let workspace = match req.method() {
+                "DELETE" => None,
+                _ => match query(req, "workspace") {
+                    Some(t) if server.workspaces.contains_key(&t) => Some(t),
+                    _ => return Response::new(400, "Bad Request", vec![]),
+                },
+            };
+
+            match decode(name).and_then(|x| server.find(&x)) {
+                Some(board) => {
+                    warn!("Board {} moved to workspace {:?}", name, workspace);
+                    board.workspace = workspace;
+                    Response::new(204, "No Content", vec![])
+                }
+                None => not_found(),
+            }
+        }
+        ["drain"] => {
+            server.draining = req.method() == "PUT";
+            server.drain_target = match server.draining {
+                true => query(req, "target"),
+                false => None,
+            };
+            Response::new(204, "No Content", vec![])
+        }
+        ["boards", name] => match decode(name) {
+            Some(ref name) if server.delete(name) => Response::new(204, "No Content", vec![]),
+            _ => not_found(),
+        },
+        ["boards", name, "latency"] => match decode(name).and_then(|x| server.find(&x)) {
+            Some(board) => json(&board.step_latency()),
+            None => not_found(),
+        },
+        ["boards", name, "history.jsonl"] => match decode(name).and_then(|x| server.find(&x)).map(|b| history::to_jsonl(b.raw_history())) {
+            Some(Ok(jsonl)) => {
+                let mut response = Response::new(200, "OK", jsonl.into_bytes());
+                response.headers_mut().push(("Content-Type".into(), b"application/x-ndjson".to_vec()));
+                response
+            }
+            Some(Err(e)) => {
+                warn!("Cannot export history of {}: {}", name, e);
+                Response::new(500, "Internal Server Error", vec![])
+            }
+            None => not_found(),
+        },
+        ["boards", name, "import"] => {
+            let path = match (&server.config.import_dir, query(req, "file")) {
+                (Some(dir), Some(file)) if !file.is_empty() && file.chars().all(|x| x.is_ascii_alphanumeric() || "._-".contains(x)) && !file.starts_with('.') => dir.join(file),
+                (Some(_), _) => return Response::new(400, "Bad Request", vec![]),
+                (None, _) => return not_found(),
+            };
+            let objects = match std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|x| import::parse(&x)) {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("Cannot import {}: {}", path.display(), e);
+                    return Response::new(400, "Bad Request", vec![]);
+                }
+            };
+
+            match decode(name).and_then(|x| server.find(&x)) {
+                Some(board) => {
+                    board.import(&objects);
+                    warn!("Imported {} objects from {} into board {}", objects.len(), path.display(), name);
+                    Response::new(204, "No Content", vec![])
+                }
+                None => not_found(),
+            }
+        }
+        ["boards", name, "trace"] => match decode(name).and_then(|x| server.find(&x)) {
+            Some(board) => {
+                board.tracing = req.method() == "PUT";
+                warn!("Tracing of board {} {}", name, if board.tracing { "enabled" } else { "disabled" });
+                Response::new(204, "No Content", vec![])
+            }
+            None => not_found(),
+        },
+        ["boards", name, "lock"] => {
+            let lock = match req.method() {
+                "DELETE" => None,
+                _ => match query(req, "state").and_then(|x| LockState::from_name(&x)) {
+                    Some(state) => Some((state, query(req, "retry_after").and_then(|x| x.parse().ok()).unwrap_or(DEFAULT_RETRY_AFTER))),
+                    None => return Response::new(400, "Bad Request", vec![]),
+                },
+            };
+
+            match decode(name).and_then(|x| server.find(&x)) {
+                Some(board) => {
+                    board.set_lock(lock);
+                    Response::new(204, "No Content", vec![])
+                }
+                None => not_found(),
+            }
+        }
+        ["quarantine", rest @ ..] => {
+            let dir = match &server.config.quarantine_dir {
+                Some(dir) => dir,
+                None => return not_found(),
+            };
+
+            let body = match rest {
+                [] => quarantine::list(dir).map(|x| x.join("\n")),
+                [id] => quarantine::replay(dir, id),
+                _ => return not_found(),
+            };
+
+            match body {
+                Ok(body) => {
+                    let mut response = Response::new(200, "OK", body.into_bytes());
+                    response.headers_mut().push(("Content-Type".into(), b"text/plain; charset=utf-8".to_vec()));
+                    response
+                }
+                Err(_) => not_found(),
+            }
+        }
+        _ => not_found(),
+    }
+}
+
+/// Finished exports are downloadable by anyone knowing the url until they
+/// expire.
+fn export(server: &Server, name: &str) -> Response {
+    let dir = match &server.config.export_dir {
+        Some(dir) => dir,
+        None => return not_found(),
+    };
+
+    match exports::load(dir, name, server.config.export_ttl) {
+        Ok(data) => {
+            let mut response = Response::new(200, "OK", data);
+            response.headers_mut().push(("Content-Type".into(), b"image/bmp".to_vec()));
+            response.headers_mut().push(("Cache-Control".into(), b"private, no-store".to_vec()));
+            response
+        }
+        Err(_) => not_found(),
+    }
+}
+
+/// Only avatars of profiles resolved for connected users are available,
+/// the original host is never contacted on request.
+fn avatar(server: &Server, user_id: &str) -> Response {
+    let avatars = match &server.avatars {
+        Some(t) => t,
+        None => return not_found(),
+    };
+
+    match decode(user_id).and_then(|x| avatars.get(&x)) {
+        Some((content_type, data)) => {
+            let mut response = Response::new(200, "OK", data.to_vec());
+            response.headers_mut().push(("Content-Type".into(), content_type.as_bytes().to_vec()));
+            response.headers_mut().push(("Cache-Control".into(), format!("public, max-age={}", avatars.ttl.as_secs()).into_bytes()));
+            response.headers_mut().push(("X-Content-Type-Options".into(), b"nosniff".to_vec()));
+            response
+        }
+        None => not_found(),
+    }
+}
+
+/// Searches all boards the user may join, optionally only boards of a
+/// single tenant. Users authenticate like on the WebSocket.
+fn search(server: &mut Server, req: &Request) -> Response {
+    let token = req.header("authorization").and_then(|x| x.strip_prefix(b"Bearer ")).and_then(|x| std::str::from_utf8(x).ok());
+    let user = match token.and_then(|x| auth(Auth { jwt_token: x }, &server.config)) {
+        Some(user) => user,
+        None => return Response::new(401, "Unauthorized", vec![]),
+    };
+    let text = match query(req, "q") {
+        Some(text) => text,
+        None => return Response::new(400, "Bad Request", vec![]),
+    };
+    let tenant = query(req, "tenant");
+
+    let mut results = vec![];
+    for (name, board) in server.boards() {
+        if tenant.as_ref().map(|x| *x != board.owner).unwrap_or(false) || server.join_role(name, &user).is_none() {
+            continue;
+        }
+        results.extend(board.search(&text).into_iter().map(|x| SearchResult {
+            board: name,
+            object_id: x.object_id,
+            position: x.position,
+        }));
+    }
+    json(&results)
+}
+
+fn metadata<'a>(name: &'a str, board: &'a Board, retention_days: u64) -> BoardMetadata<'a> {
+    BoardMetadata {
+        name,
+        owner: &board.owner,
+        private: board.private,
+        members: board.members(),
+        width: CANVAS_WIDTH,
+        height: CANVAS_HEIGHT,
+        version: board.canvas_version(),
+        expires_at: board.expires_at(retention_days),
+        bookmarks: board.bookmarks().into_iter().map(|(object_id, position, zoom, label)| Bookmark { object_id, label, position, zoom }).collect(),
+        votes: board.vote_tally(),
+        client_versions: None,
+        workspace: None,
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> Response {
+    let mut response = Response::new(200, "OK", serde_json::to_vec(value).unwrap());
+    response.headers_mut().push(("Content-Type".into(), b"application/json".to_vec()));
+    response
+}
+
+/// Admin requests must carry `X-Ob2-Timestamp` with the unix time and
+/// `X-Ob2-Signature` with hex HMAC-SHA256 of `timestamp\nmethod\nresource`
+/// when a signing secret is configured. Each signature is accepted once.
+/// The body is not signed as the handshake request of ws carries none.
+fn verify_signature(server: &mut Server, req: &Request) -> bool {
+    let secret = match &server.config.admin_signing_secret {
+        Some(secret) => secret,
+        None => return true,
+    };
+
+    let header = |name| req.header(name).and_then(|x| std::str::from_utf8(x).ok());
+    let timestamp: u64 = match header("x-ob2-timestamp").and_then(|x| x.parse().ok()) {
+        Some(t) => t,
+        None => return false,
+    };
+    let signature = match header("x-ob2-signature").and_then(from_hex) {
+        Some(t) => t,
+        None => return false,
+    };
+
+    let now = clock::now();
+    if timestamp + ADMIN_REPLAY_WINDOW < now || timestamp > now + ADMIN_REPLAY_WINDOW {
+        return false;
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(format!("{}\n{}\n{}", timestamp, req.method(), req.resource()).as_bytes());
+    if mac.verify_slice(&signature).is_err() {
+        return false;
+    }
+
+    !server.use_admin_signature(signature, timestamp)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Returns decoded value of the query parameter.
+fn query(req: &Request, key: &str) -> Option<String> {
+    let query = req.resource().splitn(2, '?').nth(1)?;
+    query.split('&').filter_map(|x| {
+        let mut pair = x.splitn(2, '=');
+        match (pair.next(), pair.next()) {
+            (Some(k), Some(v)) if k == key => decode(v),
+            _ => None,
+        }
+    }).next()
+}
+
+fn not_found() -> Response {
+    Response::new(404, "Not Found", vec![])
+}
+
+/// Decodes percent-encoded path segment.
+fn decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            decoded.push(u8::from_str_radix(segment.get(i + 1..i + 3)?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use ws::Request;
+    use crate::entitlements::Plan;
+    use crate::messages::{SetAccess, SetPrivate, Role, Text};
+    use crate::server::{Server, User};
+    use super::search;
+
+    fn search_as(server: &mut Server, token: &str) -> String {
+        let raw = format!("GET /search?q=roadmap HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n", token);
+        let req = Request::parse(raw.as_bytes()).unwrap().unwrap();
+        String::from_utf8(search(server, &req).body().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_search_access_list() {
+        let mut server = Server::new();
+        server.config.insecure_dev_auth = true;
+        let owner = User { username: "alice".to_string(), display_name: "alice".to_string(), user_id: None, plan: Plan::Free, guest: false, claims: HashMap::new(), expires_at: None, admin: false };
+        for name in &["listed", "hidden"] {
+            let board = server.create(name.to_string(), &owner);
+            board.set_private("alice", SetPrivate { private: true }).unwrap();
+            board.index_text(&Text { center: 0, text: "roadmap", text_color: 0 });
+        }
+        server.find("listed").unwrap().set_access("alice", SetAccess { username: "bob", role: Some(Role::Viewer) }).unwrap();
+
+        /* bob is not connected to any board */
+        let results = search_as(&mut server, "bob");
+        assert!(results.contains("listed") && !results.contains("hidden"));
+        assert_eq!(search_as(&mut server, "carol"), "[]");
+    }
+}
@@ -0,0 +1,57 @@
+/// Language of user facing texts sent by the server. Picked from the
+/// `Accept-Language` header of the WebSocket handshake.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Locale {
+    En,
+    Sk,
+}
+
+impl Locale {
+    /// Returns the first supported language of the header, English when
+    /// none of them is supported.
+    pub fn from_accept_language(header: &str) -> Locale {
+        for language in header.split(',') {
+            let tag = language.split(';').next().unwrap_or("").trim().to_lowercase();
+            match tag.split('-').next().unwrap_or("") {
+                "en" => return Locale::En,
+                "sk" => return Locale::Sk,
+                _ => continue,
+            }
+        }
+        Locale::En
+    }
+
+    /// Translates the error text. Texts without translation are returned
+    /// unchanged.
+    pub fn translate(self, text: &'static str) -> &'static str {
+        match self {
+            Locale::En => text,
+            Locale::Sk => match text {
+                "admin only" => "len pre administrátorov",
+                "auth expected" => "očakáva sa prihlásenie",
+                "board already exists" => "tabuľa už existuje",
+                "board deleted" => "tabuľa bola odstránená",
+                "board is full" => "tabuľa je plná",
+                "board is private" => "tabuľa je súkromná",
+                "board not found" => "tabuľa neexistuje",
+                "board quota exceeded" => "prekročený limit počtu tabúľ",
+                "cannot resume" => "reláciu nie je možné obnoviť",
+                "guests cannot create boards" => "hostia nemôžu vytvárať tabule",
+                "internal error" => "vnútorná chyba servera",
+                "invalid auth" => "neplatné prihlásenie",
+                "invalid invite" => "neplatná pozvánka",
+                "invalid view token" => "neplatný odkaz na zobrazenie",
+                "invite used up" => "pozvánka už bola použitá",
+                "join request denied" => "žiadosť o pripojenie bola zamietnutá",
+                "no owner online" => "vlastník tabule nie je pripojený",
+                "read-only access" => "prístup iba na čítanie",
+                "server is draining" => "server sa vypína, pripojte sa znova",
+                "storage quota exceeded" => "prekročený limit úložiska",
+                "token expired" => "platnosť prihlásenia vypršala",
+                "token of another user" => "prihlásenie patrí inému používateľovi",
+                "upgrade required" => "vyžaduje sa novšia verzia aplikácie",
+                _ => text,
+            },
+        }
+    }
+}
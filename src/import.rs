@@ -0,0 +1,138 @@
+//! Converts Excalidraw and tldraw scenes into board objects. Freehand
+//! strokes, lines and arrows are approximated by pixels, shapes and frames
+//! become frames and text stays text.
+
+use serde_json::Value;
+
+/// Object of the imported scene in scene coordinates. Colors are RGB.
+pub enum SceneObject {
+    Stroke { points: Vec<(f64, f64)>, color: (u8, u8, u8) },
+    Shape { x: f64, y: f64, width: f64, height: f64, title: String },
+    Text { x: f64, y: f64, text: String, color: (u8, u8, u8) },
+}
+
+impl SceneObject {
+    /// Points which must fit onto the canvas.
+    pub fn extent(&self) -> Vec<(f64, f64)> {
+        match self {
+            SceneObject::Stroke { points, .. } => points.clone(),
+            SceneObject::Shape { x, y, width, height, .. } => vec![(*x, *y), (x + width, y + height)],
+            SceneObject::Text { x, y, .. } => vec![(*x, *y)],
+        }
+    }
+}
+
+/// Parses Excalidraw scene (`elements`) or tldraw document (`records`
+/// or `store`), the format is detected from the content.
+pub fn parse(json: &str) -> Result<Vec<SceneObject>, String> {
+    let scene: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if let Some(elements) = scene["elements"].as_array() {
+        return Ok(elements.iter().filter(|x| !x["isDeleted"].as_bool().unwrap_or(false)).filter_map(excalidraw).collect());
+    }
+
+    let records: Vec<&Value> = match (&scene["records"], &scene["store"], &scene["document"]["store"]) {
+        (Value::Array(x), _, _) => x.iter().collect(),
+        (_, Value::Object(x), _) | (_, _, Value::Object(x)) => x.values().collect(),
+        _ => return Err("neither Excalidraw nor tldraw scene".to_string()),
+    };
+    Ok(records.into_iter().filter(|x| x["typeName"] == "shape").filter_map(tldraw).collect())
+}
+
+fn excalidraw(element: &Value) -> Option<SceneObject> {
+    let (x, y) = (element["x"].as_f64()?, element["y"].as_f64()?);
+    let (width, height) = (element["width"].as_f64().unwrap_or(0.0), element["height"].as_f64().unwrap_or(0.0));
+    let color = element["strokeColor"].as_str().and_then(hex_color).unwrap_or((0, 0, 0));
+
+    match element["type"].as_str()? {
+        "freedraw" | "line" | "arrow" => Some(SceneObject::Stroke {
+            points: element["points"].as_array()?.iter()
+                .filter_map(|p| Some((x + p[0].as_f64()?, y + p[1].as_f64()?)))
+                .collect(),
+            color,
+        }),
+        "text" => Some(SceneObject::Text {
+            x: x + width / 2.0,
+            y: y + height / 2.0,
+            text: element["text"].as_str()?.to_string(),
+            color,
+        }),
+        "rectangle" | "ellipse" | "diamond" | "frame" => Some(SceneObject::Shape {
+            x,
+            y,
+            width,
+            height,
+            title: element["name"].as_str().unwrap_or("").to_string(),
+        }),
+        _ => None,
+    }
+}
+
+fn tldraw(shape: &Value) -> Option<SceneObject> {
+    let (x, y) = (shape["x"].as_f64()?, shape["y"].as_f64()?);
+    let props = &shape["props"];
+    let color = props["color"].as_str().map(named_color).unwrap_or((0, 0, 0));
+    let point = |p: &Value| Some((x + p["x"].as_f64()?, y + p["y"].as_f64()?));
+
+    match shape["type"].as_str()? {
+        "draw" | "highlight" => Some(SceneObject::Stroke {
+            points: props["segments"].as_array()?.iter()
+                .filter_map(|s| s["points"].as_array())
+                .flatten()
+                .filter_map(point)
+                .collect(),
+            color,
+        }),
+        "line" => Some(SceneObject::Stroke {
+            points: match &props["points"] {
+                Value::Array(points) => points.iter().filter_map(point).collect(),
+                Value::Object(points) => {
+                    /* newer documents key points by fractional index */
+                    let mut points: Vec<(&String, &Value)> = points.iter().collect();
+                    points.sort_by(|a, b| a.1["index"].as_str().cmp(&b.1["index"].as_str()));
+                    points.into_iter().filter_map(|(_, p)| point(p)).collect()
+                }
+                _ => return None,
+            },
+            color,
+        }),
+        "arrow" => Some(SceneObject::Stroke {
+            points: vec![point(&props["start"])?, point(&props["end"])?],
+            color,
+        }),
+        "text" => Some(SceneObject::Text {
+            x,
+            y,
+            text: props["text"].as_str()?.to_string(),
+            color,
+        }),
+        "geo" | "frame" | "note" => Some(SceneObject::Shape {
+            x,
+            y,
+            width: props["w"].as_f64().unwrap_or(200.0),
+            height: props["h"].as_f64().unwrap_or(200.0),
+            title: props["text"].as_str().or_else(|| props["name"].as_str()).unwrap_or("").to_string(),
+        }),
+        _ => None,
+    }
+}
+
+fn hex_color(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.strip_prefix('#')?;
+    let component = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((component(0)?, component(2)?, component(4)?))
+}
+
+/// Approximate RGB of tldraw named colors.
+fn named_color(name: &str) -> (u8, u8, u8) {
+    match name {
+        "red" | "light-red" => (224, 49, 49),
+        "green" | "light-green" => (9, 146, 104),
+        "blue" | "light-blue" => (66, 99, 235),
+        "yellow" => (255, 196, 0),
+        "orange" => (247, 103, 7),
+        "violet" | "light-violet" => (174, 62, 201),
+        "grey" => (173, 181, 189),
+        "white" => (255, 255, 255),
+        _ => (0, 0, 0),
+    }
+}
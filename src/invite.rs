@@ -0,0 +1,114 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::messages::Role;
+use crate::error::Error;
+use crate::clock::now;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Claims of an invite token. Tokens are `hex(payload).hex(signature)`
+/// where the signature is HMAC-SHA256 of the payload.
+pub struct Invite {
+    pub board_name: String,
+    pub role: Role,
+    pub expires_at: u64,
+    pub max_uses: u16,
+    pub nonce: u32,
+}
+
+/// Claims of a view-only token granting spectator access to a board
+/// without user authentication. The payload starts with `view` so that
+/// invite and view tokens cannot be used in place of each other.
+pub struct ViewToken {
+    pub board_name: String,
+    pub expires_at: u64,
+}
+
+impl Invite {
+    pub fn sign(&self, secret: &[u8]) -> String {
+        let role = self.role as u8;
+        sign(format!("{}|{}|{}|{}|{}", role, self.expires_at, self.max_uses, self.nonce, self.board_name), secret)
+    }
+
+    pub fn verify(token: &str, secret: &[u8]) -> Result<Invite, Error> {
+        let invalid = || Error::Message("invalid invite token".to_string());
+
+        let payload = verify(token, secret).ok_or_else(invalid)?;
+        let fields: Vec<&str> = payload.splitn(5, '|').collect();
+        if fields.len() != 5 {
+            return Err(invalid());
+        }
+
+        let invite = Invite {
+            role: fields[0].parse().ok().and_then(Role::from_u8).ok_or_else(invalid)?,
+            expires_at: fields[1].parse().map_err(|_| invalid())?,
+            max_uses: fields[2].parse().map_err(|_| invalid())?,
+            nonce: fields[3].parse().map_err(|_| invalid())?,
+            board_name: fields[4].to_string(),
+        };
+
+        if invite.expires_at < now() {
+            return Err(Error::Message("invite token expired".to_string()));
+        }
+
+        Ok(invite)
+    }
+}
+
+impl ViewToken {
+    pub fn sign(&self, secret: &[u8]) -> String {
+        sign(format!("view|{}|{}", self.expires_at, self.board_name), secret)
+    }
+
+    pub fn verify(token: &str, secret: &[u8]) -> Result<ViewToken, Error> {
+        let invalid = || Error::Message("invalid view token".to_string());
+
+        let payload = verify(token, secret).ok_or_else(invalid)?;
+        let fields: Vec<&str> = payload.splitn(3, '|').collect();
+        if fields.len() != 3 || fields[0] != "view" {
+            return Err(invalid());
+        }
+
+        let view_token = ViewToken {
+            expires_at: fields[1].parse().map_err(|_| invalid())?,
+            board_name: fields[2].to_string(),
+        };
+
+        if view_token.expires_at < now() {
+            return Err(Error::Message("view token expired".to_string()));
+        }
+
+        Ok(view_token)
+    }
+}
+
+fn sign(payload: String, secret: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(payload.as_bytes());
+    format!("{}.{}", to_hex(payload.as_bytes()), to_hex(&mac.finalize().into_bytes()))
+}
+
+/// Returns the payload of the token when its signature is valid.
+fn verify(token: &str, secret: &[u8]) -> Option<String> {
+    let mut parts = token.splitn(2, '.');
+    let payload = parts.next().and_then(from_hex)?;
+    let signature = parts.next().and_then(from_hex)?;
+
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(&payload);
+    mac.verify_slice(&signature).ok()?;
+
+    String::from_utf8(payload).ok()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
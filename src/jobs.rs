@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use serde::Serialize;
+use log::warn;
+use crate::clock;
+
+/// Number of finished jobs whose status is kept.
+const MAX_FINISHED_JOBS: usize = 128;
+
+pub type JobId = u32;
+
+type Job = Box<dyn FnOnce(JobId) -> Result<(), String> + Send>;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct JobStatus {
+    pub id: JobId,
+    pub name: String,
+    pub state: JobState,
+    pub queued_at: u64,
+    pub finished_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Runs heavy work off the connection handlers on a dedicated worker
+/// thread. Jobs run one at a time in the order they were submitted and
+/// receive their own id.
+pub struct Scheduler {
+    queue: Sender<(JobId, Job)>,
+    statuses: Arc<Mutex<VecDeque<JobStatus>>>,
+    last_job_id: JobId,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        let (queue, jobs) = mpsc::channel::<(JobId, Job)>();
+        let statuses = Arc::new(Mutex::new(VecDeque::new()));
+
+        let worker_statuses = statuses.clone();
+        thread::Builder::new().name("jobs".to_string()).spawn(move || {
+            for (id, job) in jobs {
+                update(&worker_statuses, id, |x| x.state = JobState::Running);
+                let result = job(id);
+                update(&worker_statuses, id, |x| {
+                    x.finished_at = Some(clock::now());
+                    match result {
+                        Ok(_) => x.state = JobState::Done,
+                        Err(ref e) => {
+                            warn!("Job {} ({}) failed: {}", x.id, x.name, e);
+                            x.state = JobState::Failed;
+                            x.error = Some(e.clone());
+                        }
+                    }
+                });
+            }
+        }).expect("cannot start job worker");
+
+        Scheduler {
+            queue,
+            statuses,
+            last_job_id: 0,
+        }
+    }
+
+    pub fn submit<F>(&mut self, name: &str, job: F) -> JobId where F: FnOnce(JobId) -> Result<(), String> + Send + 'static {
+        self.last_job_id = self.last_job_id.wrapping_add(1);
+        let id = self.last_job_id;
+
+        {
+            let mut statuses = self.statuses.lock().unwrap();
+            while statuses.len() >= MAX_FINISHED_JOBS && statuses.front().map(|x| x.finished_at.is_some()).unwrap_or(false) {
+                statuses.pop_front();
+            }
+            statuses.push_back(JobStatus {
+                id,
+                name: name.to_string(),
+                state: JobState::Queued,
+                queued_at: clock::now(),
+                finished_at: None,
+                error: None,
+            });
+        }
+
+        if self.queue.send((id, Box::new(job))).is_err() {
+            update(&self.statuses, id, |x| {
+                x.state = JobState::Failed;
+                x.error = Some("job worker is not running".to_string());
+            });
+        }
+        id
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.statuses.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn update<F>(statuses: &Mutex<VecDeque<JobStatus>>, id: JobId, f: F) where F: FnOnce(&mut JobStatus) {
+    if let Some(status) = statuses.lock().unwrap().iter_mut().find(|x| x.id == id) {
+        f(status);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rsa::{BigUint, RsaPublicKey};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Deserialize;
+use log::{info, warn};
+
+/// Fetches triggered by tokens with unknown key ids are at least this far
+/// apart so forged tokens cannot flood the identity provider.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signing keys of the identity provider by their key id.
+static KEYS: RwLock<Option<HashMap<String, RsaPublicKey>>> = RwLock::new(None);
+static REFRESH_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Deserialize)]
+struct KeySet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// Starts the thread keeping the keys from the JWKS url fresh. Keys are
+/// refetched every `refresh` seconds and when a token signed by an unknown
+/// key arrives, which is how rotated keys are picked up early.
+pub fn start(url: String, refresh: u64) {
+    thread::spawn(move || {
+        let mut last_fetch: Option<Instant> = None;
+        loop {
+            let due = match last_fetch {
+                None => true,
+                Some(t) => t.elapsed() >= Duration::from_secs(refresh)
+                    || (REFRESH_REQUESTED.load(Ordering::SeqCst) && t.elapsed() >= MIN_REFRESH_INTERVAL),
+            };
+
+            if due {
+                REFRESH_REQUESTED.store(false, Ordering::SeqCst);
+                last_fetch = Some(Instant::now());
+                match fetch(&url) {
+                    Ok(keys) => {
+                        info!("Fetched {} signing keys from {}", keys.len(), url);
+                        *KEYS.write().unwrap() = Some(keys);
+                    }
+                    /* previously fetched keys stay valid until the next success */
+                    Err(e) => warn!("Cannot fetch signing keys from {}: {}", url, e),
+                }
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
+
+pub fn key(kid: &str) -> Option<RsaPublicKey> {
+    let key = KEYS.read().unwrap().as_ref().and_then(|x| x.get(kid).cloned());
+    if key.is_none() {
+        REFRESH_REQUESTED.store(true, Ordering::SeqCst);
+    }
+    key
+}
+
+fn fetch(url: &str) -> Result<HashMap<String, RsaPublicKey>, String> {
+    let body = ureq::get(url)
+        .timeout(FETCH_TIMEOUT)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())?;
+    parse(&body)
+}
+
+/// Keys other than RSA ones with an id are skipped.
+fn parse(body: &str) -> Result<HashMap<String, RsaPublicKey>, String> {
+    let set: KeySet = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let mut keys = HashMap::new();
+    for jwk in set.keys {
+        let (kid, n, e) = match (jwk.kty.as_str(), jwk.kid, jwk.n, jwk.e) {
+            ("RSA", Some(kid), Some(n), Some(e)) => (kid, n, e),
+            _ => continue,
+        };
+
+        let component = |x: &str| URL_SAFE_NO_PAD.decode(x).map(|x| BigUint::from_bytes_be(&x)).map_err(|e| e.to_string());
+        let key = RsaPublicKey::new(component(&n)?, component(&e)?).map_err(|e| e.to_string())?;
+        keys.insert(kid, key);
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse_key_set() {
+        let body = r#"{"keys":[
+            {"kty":"RSA","kid":"2024","use":"sig","alg":"RS256","e":"AQAB","n":"0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw"},
+            {"kty":"EC","kid":"ec","crv":"P-256","x":"f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU","y":"x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"}
+        ]}"#;
+        let keys = parse(body).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys.contains_key("2024"));
+    }
+}
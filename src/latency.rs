@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use serde::Serialize;
+use crate::clock;
+
+/// Upper bounds of histogram buckets in microseconds, the last bucket
+/// holds everything slower.
+const BUCKETS: [u64; 10] = [100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000];
+/// Steps should reach every client of the board within this time.
+pub const TARGET: Duration = Duration::from_millis(50);
+/// Fraction of steps which must be delivered within the target.
+pub const SLO_OBJECTIVE: f64 = 0.99;
+/// Burn rate is computed over this many most recent minutes.
+const BURN_WINDOW_MINUTES: usize = 60;
+
+/// Time from receiving a step to handing it over to the last client of
+/// the board, and how fast the latency objective is being used up.
+pub struct StepLatency {
+    counts: [u64; BUCKETS.len() + 1],
+    sum_us: u64,
+    /// Steps and steps slower than the target per unix minute.
+    minutes: VecDeque<(u64, u64, u64)>,
+}
+
+#[derive(Serialize)]
+pub struct Bucket {
+    /// Upper bound in microseconds, `None` for the overflow bucket.
+    pub le: Option<u64>,
+    pub count: u64,
+}
+
+#[derive(Serialize)]
+pub struct LatencyReport {
+    pub buckets: Vec<Bucket>,
+    pub count: u64,
+    pub sum_us: u64,
+    pub target_ms: u64,
+    pub objective: f64,
+    /// Share of the error budget used per unit of time within the burn
+    /// window, 1.0 exhausts the budget exactly at the objective.
+    pub burn_rate: f64,
+}
+
+impl StepLatency {
+    pub fn new() -> Self {
+        StepLatency {
+            counts: [0; BUCKETS.len() + 1],
+            sum_us: 0,
+            minutes: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let us = latency.as_micros() as u64;
+        let bucket = BUCKETS.iter().position(|x| us <= *x).unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum_us += us;
+
+        let minute = clock::now() / 60;
+        if self.minutes.back().map(|x| x.0) != Some(minute) {
+            self.minutes.push_back((minute, 0, 0));
+        }
+        while self.minutes.front().map(|x| x.0 + (BURN_WINDOW_MINUTES as u64) <= minute).unwrap_or(false) {
+            self.minutes.pop_front();
+        }
+
+        let current = self.minutes.back_mut().unwrap();
+        current.1 += 1;
+        if latency > TARGET {
+            current.2 += 1;
+        }
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        let (total, slow) = self.minutes.iter().fold((0, 0), |acc, x| (acc.0 + x.1, acc.1 + x.2));
+        let burn_rate = match total {
+            0 => 0.0,
+            _ => slow as f64 / total as f64 / (1.0 - SLO_OBJECTIVE),
+        };
+
+        LatencyReport {
+            buckets: self.counts.iter().enumerate().map(|(i, count)| Bucket {
+                le: BUCKETS.get(i).cloned(),
+                count: *count,
+            }).collect(),
+            count: self.counts.iter().sum(),
+            sum_us: self.sum_us,
+            target_ms: TARGET.as_millis() as u64,
+            objective: SLO_OBJECTIVE,
+            burn_rate,
+        }
+    }
+}
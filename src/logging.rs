@@ -0,0 +1,61 @@
+use std::env;
+use std::io::Write;
+use std::sync::RwLock;
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use log::{Log, Metadata, Record, LevelFilter};
+use crate::request;
+
+/// Target of per-board tracing. Records of this target are emitted
+/// regardless of the filter as tracing is enabled per board explicitly.
+pub const TRACE_TARGET: &str = "board_trace";
+
+/// Active filter along with the `RUST_LOG` style spec it was built from.
+static FILTER: RwLock<Option<(String, Filter)>> = RwLock::new(None);
+
+/// Formats records with env_logger and filters them with a filter which
+/// can be replaced at runtime.
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == TRACE_TARGET || FILTER.read().unwrap().as_ref().map(|x| x.1.enabled(metadata)).unwrap_or(false)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() == TRACE_TARGET || FILTER.read().unwrap().as_ref().map(|x| x.1.matches(record)).unwrap_or(false) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Installs the logger with the filter from `RUST_LOG`.
+pub fn init() {
+    set_filter(&env::var("RUST_LOG").unwrap_or_default());
+
+    let inner = env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .format(|buf, record| match request::current() {
+            Some(id) => writeln!(buf, "[{} {} {} {}] {}", buf.timestamp(), record.level(), record.target(), id, record.args()),
+            None => writeln!(buf, "[{} {} {}] {}", buf.timestamp(), record.level(), record.target(), record.args()),
+        })
+        .build();
+    log::set_boxed_logger(Box::new(Logger { inner })).unwrap();
+}
+
+/// Replaces the filter, `spec` uses the `RUST_LOG` syntax such as
+/// `info,ob2_communication::client=debug`.
+pub fn set_filter(spec: &str) {
+    let filter = FilterBuilder::new().parse(spec).build();
+    log::set_max_level(filter.filter().max(LevelFilter::Info));
+    *FILTER.write().unwrap() = Some((spec.to_string(), filter));
+}
+
+pub fn filter() -> String {
+    FILTER.read().unwrap().as_ref().map(|x| x.0.clone()).unwrap_or_default()
+}
@@ -1,6 +1,7 @@
-use ws::listen;
-use crate::client::Client;
-use log::info;
+use ws::WebSocket;
+use crate::client::SERVER;
+use crate::housekeeping::Connections;
+use log::{info, warn};
 
 mod error;
 mod ser;
@@ -9,12 +10,67 @@ mod messages;
 mod client;
 mod server;
 mod auth;
+mod poll;
+mod objects;
+mod canvas;
+mod config;
+mod entitlements;
+mod invite;
+mod clock;
+mod http;
+mod request;
+mod quarantine;
+mod capture;
+mod i18n;
+mod jobs;
+mod exports;
+mod latency;
+mod logging;
+mod features;
+mod history;
+mod import;
+mod transport;
+mod outbox;
+mod search;
+mod mention;
+mod normalize;
+mod palettes;
+mod jwks;
+mod templates;
+mod schedules;
+mod workspaces;
+mod profiles;
+mod avatars;
+mod timeline;
+mod housekeeping;
+#[cfg(test)]
+mod simulation;
 
 fn main() {
-    env_logger::init();
+    logging::init();
 
-    info!("Starting WebSocket server...");
-    listen("0.0.0.0:3013", |out| {
-        Client { board_context: None, authenticated_user: None, out }
-    }).unwrap()
+    SERVER.with(|x| {
+        let config = &x.borrow().config;
+        match (config.has_token_key(), config.insecure_dev_auth) {
+            (false, true) => warn!("OB2_INSECURE_DEV_AUTH is set, user tokens are taken as usernames without verification"),
+            (false, false) => warn!("No key for user tokens is configured, only guests can authenticate"),
+            (true, true) => warn!("OB2_INSECURE_DEV_AUTH is ignored as a key for user tokens is configured"),
+            (true, false) => {}
+        }
+    });
+
+    let jwks = SERVER.with(|x| {
+        let config = &x.borrow().config;
+        config.jwks_url.clone().map(|url| (url, config.jwks_refresh))
+    });
+    if let Some((url, refresh)) = jwks {
+        jwks::start(url, refresh);
+    }
+
+    let address = SERVER.with(|x| x.borrow().config.listen.clone());
+    info!("Starting WebSocket server on {}...", address);
+    let mut socket = WebSocket::new(Connections).and_then(|x| x.bind(address)).unwrap();
+    let url = housekeeping::loopback_url(socket.local_addr().unwrap());
+    socket.connect(url).unwrap();
+    socket.run().unwrap();
 }
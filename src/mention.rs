@@ -0,0 +1,30 @@
+/// Usernames mentioned in the text as `@username`, each at most once.
+pub fn parse(text: &str) -> Vec<&str> {
+    let mut mentions = vec![];
+    for (i, _) in text.match_indices('@') {
+        /* e-mail addresses are not mentions */
+        if text[..i].chars().next_back().map(|x| x.is_alphanumeric()).unwrap_or(false) {
+            continue;
+        }
+
+        let rest = &text[i + 1..];
+        let end = rest.find(|x: char| !(x.is_alphanumeric() || "._-".contains(x))).unwrap_or(rest.len());
+        let username = rest[..end].trim_end_matches('.');
+        if !username.is_empty() && !mentions.contains(&username) {
+            mentions.push(username);
+        }
+    }
+    mentions
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse_mentions() {
+        assert_eq!(parse("@alice please check with @bob.smith."), vec!["alice", "bob.smith"]);
+        assert_eq!(parse("mail alice@example.com or @alice @alice"), vec!["alice"]);
+        assert!(parse("@ nothing").is_empty());
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Serialize, Deserialize};
 use bitflags::bitflags;
 
-const fn color(r: u8, g: u8, b: u8) -> u32 {
+pub const fn color(r: u8, g: u8, b: u8) -> u32 {
     return (b as u32) << 16 | (g as u32) << 8 | r as u32;
 }
 
@@ -23,6 +23,9 @@ pub type Color = u8;
 pub type Position = u32;
 pub type UserId = u8;
 pub type StepId = u32;
+pub type PollId = u16;
+pub type ObjectId = u32;
+pub type StickerId = u16;
 
 /* custom types */
 bitflags! {
@@ -30,12 +33,129 @@ bitflags! {
     pub struct BoardFlags: u8 {
         const HISTORY_ENABLED = 0b00000001;
         const HISTORY_TRIMMED = 0b00000010;
+        const PRIVATE = 0b00000100;
+    }
+}
+
+bitflags! {
+    /// Categories of events a client wants to be notified about.
+    #[derive(Serialize, Deserialize)]
+    pub struct NotificationFlags: u8 {
+        const PRESENCE = 0b00000001;
+        const POLLS = 0b00000010;
+        const CURSORS = 0b00000100;
+        const TYPING = 0b00001000;
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct DrawFlags(pub u8);
 
+impl DrawFlags {
+    /// Index into the brush catalog of the board configuration.
+    pub fn brush(&self) -> u8 {
+        self.0 & 0b0000_0111
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        match self.0 & 0b0001_1000 {
+            0b0000_1000 => BlendMode::Multiply,
+            _ => BlendMode::Alpha,
+        }
+    }
+}
+
+/// How a translucent brush composes with the pixels below it. Multiply
+/// keeps dark strokes underneath visible, like a real highlighter.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum BlendMode {
+    Alpha,
+    Multiply,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn from_u8(value: u8) -> Option<Role> {
+        match value {
+            0 => Some(Role::Viewer),
+            1 => Some(Role::Editor),
+            2 => Some(Role::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Role> {
+        match name {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum PresenceState {
+    Active,
+    Away,
+    DoNotDisturb,
+}
+
+/// Maintenance operation during which the board rejects mutations.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum LockState {
+    Compacting,
+    Restoring,
+    Migrating,
+}
+
+impl LockState {
+    pub fn from_name(name: &str) -> Option<LockState> {
+        match name {
+            "compacting" => Some(LockState::Compacting),
+            "restoring" => Some(LockState::Restoring),
+            "migrating" => Some(LockState::Migrating),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LockState::Compacting => "compacting",
+            LockState::Restoring => "restoring",
+            LockState::Migrating => "migrating",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Bounds {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Grid {
+    pub cell_size: u16,
+    pub visible: bool,
+    pub snap: bool,
+}
+
+/// Round brush stamped at every drawn position. Soft brushes fade out
+/// towards the edge, alpha of 255 is fully opaque.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Brush {
+    pub radius: u8,
+    pub alpha: u8,
+    pub soft: bool,
+}
+
 /* messages */
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -55,11 +175,18 @@ pub struct Join<'a> {
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct BoardConfiguration {
+pub struct BoardConfiguration<'a> {
     pub palette: Palette,
     pub background: Color,
     pub board_flags: BoardFlags,
     pub history_size: u16,
+    pub vote_quota: u8,
+    #[serde(borrow)]
+    pub stickers: Vec<&'a str>,
+    pub grid: Option<Grid>,
+    #[serde(borrow)]
+    pub background_image: Option<&'a str>,
+    pub brushes: Vec<Brush>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -67,105 +194,1585 @@ pub struct Step {
     pub step_id: StepId
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct Draw {
-    pub position: Position,
-    pub color: Color,
-    pub flags: DrawFlags,
-}
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Draw {
+    pub position: Position,
+    pub color: Color,
+    pub flags: DrawFlags,
+}
+
+/// Cubic Bezier segment continuing from the end of the previous one.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct CurveSegment {
+    pub control1: Position,
+    pub control2: Position,
+    pub end: Position,
+}
+
+/// Smoothed stroke sent as curve segments instead of individual pixels.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CurveStroke {
+    pub color: Color,
+    pub start: Position,
+    pub segments: Vec<CurveSegment>,
+}
+
+/// Resets pixels in the bounds to the background color.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ClearRegion {
+    pub bounds: Bounds,
+}
+
+/// Resets the whole canvas to the background color. Texts, images and
+/// objects are kept.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ClearAll;
+
+/// Encoded mutations applied, stored and replayed as a single step, such
+/// as all objects of a paste. Groups cannot be nested.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Group<'a> {
+    pub data: &'a [u8],
+}
+
+/// Asks for the objects in a clipboard blob, answered with `Clipboard`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CopyObjects {
+    pub ids: Vec<ObjectId>,
+}
+
+/// Copied objects as encoded create messages. Clients keep the blob
+/// opaque and may paste it into any board.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Clipboard<'a> {
+    pub blob: &'a [u8],
+}
+
+/// Creates objects of the clipboard blob with fresh ids, moved by the
+/// offset in pixels.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct PasteObjects<'a> {
+    pub blob: &'a [u8],
+    pub offset_x: i16,
+    pub offset_y: i16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct TemplateVariable<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+/// Creates a board like `Create`, replacing `{{name}}` placeholders of the
+/// template with the variables.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CreateFromTemplate<'a> {
+    pub template_id: u64,
+    pub name: &'a str,
+    #[serde(borrow)]
+    pub variables: Vec<TemplateVariable<'a>>,
+}
+
+/// Object leading to another board. Ids are assigned by the server like
+/// for stamps.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Portal<'a> {
+    pub object_id: ObjectId,
+    pub position: Position,
+    pub target_board: &'a str,
+}
+
+/// Moves the connection to the target board of the portal when the user
+/// may join it, otherwise the reason is sent as `ServerMessage`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct EnterPortal {
+    pub object_id: ObjectId,
+}
+
+/// New token of an authenticated client, sent before the current one
+/// expires. Clients with a lapsed token are disconnected.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ReAuth<'a> {
+    pub jwt_token: &'a str,
+}
+
+/// Sent after joining a board. Presenting the token in `Resume` shortly
+/// after a disconnect reattaches the client under the same user id.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ResumeToken {
+    pub token: u64,
+}
+
+/// Rejoins the board left by a disconnect. Only the history published
+/// since then is sent, unless it is no longer available.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Resume<'a> {
+    pub board_name: &'a str,
+    pub token: u64,
+}
+
+/// Sent to a client which authenticated as a guest with its generated
+/// username.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct GuestIdentity<'a> {
+    pub username: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CursorMove {
+    pub position: Position,
+    pub user_id: UserId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Fill {
+    pub start: Position,
+    pub end: Position,
+    pub color: Color,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Image<'a> {
+    pub start: Position,
+    pub end: Position,
+    pub url: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Text<'a> {
+    pub center: Position,
+    pub text: &'a str,
+    pub text_color: Color,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Undo {
+    pub last_actual_step_id: StepId
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Ping {
+    pub timestamp: u64
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct UserJoin<'a> {
+    pub user_id: UserId,
+    pub username: &'a str,
+}
+
+/// Display name and avatar of a connected user, sent after `UserJoin`
+/// once the profile service resolved them. With the avatar proxy enabled
+/// `avatar_url` is a path on this server, empty when there is no avatar.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct UserProfile<'a> {
+    pub user_id: UserId,
+    pub display_name: &'a str,
+    pub avatar_url: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct UserLeave {
+    pub user_id: UserId
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ServerMessage<'a> {
+    pub message: &'a str
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct History<'a> {
+    pub data: &'a [u8]
+}
+
+/// Encoded events which happened while the user was not connected, such as
+/// mentions. Split into multiple messages like the history.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct MissedEvents<'a> {
+    pub data: &'a [u8]
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CreatePoll<'a> {
+    pub poll_id: PollId,
+    pub question: &'a str,
+    #[serde(borrow)]
+    pub options: Vec<&'a str>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Vote {
+    pub poll_id: PollId,
+    pub option: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ClosePoll {
+    pub poll_id: PollId
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct PollResults {
+    pub poll_id: PollId,
+    pub closed: bool,
+    pub votes: Vec<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct PlaceVote {
+    pub position: Position,
+    pub user_id: UserId,
+}
+
+/// Number of votes placed at the position.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct VoteTally {
+    pub position: Position,
+    pub votes: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Stamp {
+    pub object_id: ObjectId,
+    pub position: Position,
+    pub sticker_id: StickerId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetGrid {
+    pub grid: Option<Grid>
+}
+
+/// Switches the board to one of the palette presets, owner only.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetPalettePreset {
+    pub preset: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetBackground<'a> {
+    #[serde(borrow)]
+    pub url: Option<&'a str>
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Connector {
+    pub object_id: ObjectId,
+    pub from_object: ObjectId,
+    pub to_object: ObjectId,
+    pub style: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CreateFrame<'a> {
+    pub object_id: ObjectId,
+    pub bounds: Bounds,
+    pub title: &'a str,
+}
+
+/// Sent only to the user mentioned as `@username` in a text of the author.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Mention<'a> {
+    pub user_id: UserId,
+    pub position: Position,
+    pub text: &'a str,
+}
+
+/// Named waypoint of the board. Zoom is in percent.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CreateBookmark<'a> {
+    pub object_id: ObjectId,
+    pub position: Position,
+    pub zoom: u16,
+    pub label: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct JumpToFrame {
+    pub object_id: ObjectId
+}
+
+/// Sent by clients whenever their viewport changes, the server relays it
+/// to followers of the user.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Viewport {
+    pub user_id: UserId,
+    pub bounds: Bounds,
+}
+
+/// Asks for the last known viewport of another member.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestUserViewport {
+    pub user_id: UserId,
+}
+
+/// Starts following viewport of the user, `None` stops following.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Follow {
+    pub user_id: Option<UserId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Minimap<'a> {
+    pub width: u16,
+    pub height: u16,
+    pub data: &'a [u8],
+    /// Positions of all bookmarks of the board.
+    pub bookmarks: Vec<Position>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestRegion {
+    pub bounds: Bounds
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RegionPatch<'a> {
+    pub bounds: Bounds,
+    pub data: &'a [u8],
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CreateInvite {
+    pub role: Role,
+    pub expires_in: u32,
+    pub max_uses: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Invite<'a> {
+    pub token: &'a str
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct JoinInvite<'a> {
+    pub token: &'a str
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct CreateViewToken {
+    pub expires_in: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ViewToken<'a> {
+    pub token: &'a str
+}
+
+/// Enters the board as a spectator using a view token. Sent instead of
+/// `Auth` as the first message.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct JoinView<'a> {
+    pub token: &'a str
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetPrivate {
+    pub private: bool
+}
+
+/// Grants the user, named by the subject of their token, a role in the
+/// board, `None` revokes it. Boards with an access list only admit their
+/// owner, listed users and members of their workspace. Only the owner may
+/// change the list.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetAccess<'a> {
+    pub username: &'a str,
+    pub role: Option<Role>,
+}
+
+/// Shows the text to everyone connected to this server as `ServerMessage`.
+/// Allowed to configured admins only.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct AdminBroadcast<'a> {
+    pub message: &'a str,
+}
+
+/// Deletes the board and disconnects its clients. Allowed to configured
+/// admins only.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct AdminDeleteBoard<'a> {
+    pub name: &'a str,
+}
+
+/// Asks for the recent activity of the board, answered with `Timeline`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestTimeline;
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TimelineKind {
+    Join,
+    Leave,
+    Clear,
+    Paste,
+    Import,
+    Lock,
+    Unlock,
+}
+
+/// Repeated events of a user are merged into one with their `count`, the
+/// time is the unix time of the last one.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct TimelineEvent<'a> {
+    pub time: u64,
+    pub kind: TimelineKind,
+    pub username: &'a str,
+    pub detail: &'a str,
+    pub count: u16,
+}
+
+/// Notable events of the board from the oldest one, such as joins, clears
+/// or locks for maintenance.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Timeline<'a> {
+    #[serde(borrow)]
+    pub events: Vec<TimelineEvent<'a>>,
+}
+
+/// Asks for the access list of the board, allowed to the owner only.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestAccessList;
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct AccessEntry<'a> {
+    pub username: &'a str,
+    pub role: Role,
+}
+
+/// Sent to the owner on request and after every change of the list.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct AccessList<'a> {
+    #[serde(borrow)]
+    pub entries: Vec<AccessEntry<'a>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestJoin<'a> {
+    pub name: &'a str
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct JoinRequest<'a> {
+    pub request_id: u16,
+    pub username: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RespondJoin {
+    pub request_id: u16,
+    pub approve: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetNotifications {
+    pub flags: NotificationFlags
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetPresence {
+    pub user_id: UserId,
+    pub state: PresenceState,
+}
+
+/// Context is the object being edited or `None` for the chat.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Typing {
+    pub user_id: UserId,
+    pub context: Option<ObjectId>,
+    pub active: bool,
+}
+
+/// Times are milliseconds since unix epoch. Server fills in its receive
+/// and send time and returns the message to the client.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct TimeSync {
+    pub client_send_time: u64,
+    pub server_receive_time: u64,
+    pub server_send_time: u64,
+}
+
+/// Envelope attaching a client chosen idempotency key to an encoded
+/// mutating message. Messages with a key already seen from the same user
+/// are ignored, so clients can safely resend until acknowledged.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Idempotent<'a> {
+    pub key: u32,
+    pub message: &'a [u8],
+}
+
+/// Subscribes to semantic descriptions of board objects intended for
+/// screen readers. Existing objects are described when enabled.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SetAccessibility {
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct AccessibilityEvent<'a> {
+    pub object_id: ObjectId,
+    pub description: &'a str,
+}
+
+/// Number of frames of the connection the server has processed so far,
+/// counting from one. Frames are processed in order, so every frame up to
+/// `sequence` is applied and clients only need to resend later ones.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct AckWindow {
+    pub sequence: u32,
+}
+
+/// Envelope tagging a mutating message with an id chosen by the client
+/// for its optimistic rendering. Answered with `StepAssigned`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Provisional<'a> {
+    pub provisional_id: u32,
+    pub message: &'a [u8],
+}
+
+/// Maps the provisional id onto the step id assigned by the server, or
+/// `None` when the message was rejected and should be rolled back.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct StepAssigned {
+    pub provisional_id: u32,
+    pub step_id: Option<StepId>,
+}
+
+/// Broadcast when the board is locked or unlocked, and sent back in place
+/// of rejected mutations. Clients should retry after `retry_after` seconds.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct BoardLock {
+    pub state: Option<LockState>,
+    pub retry_after: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestSnapshot;
+
+/// Header of a snapshot update, followed by `tiles` region patches which
+/// turn the `base_version` snapshot into `version`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SnapshotDelta {
+    pub base_version: u32,
+    pub version: u32,
+    pub tiles: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestExport;
+
+/// Sent to the requester of an export while its job runs.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ExportProgress {
+    pub job_id: u32,
+    pub percent: u8,
+}
+
+/// The export is finished and can be downloaded from `url` until it
+/// expires.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ExportReady<'a> {
+    pub job_id: u32,
+    pub url: &'a str,
+    pub votes: Vec<VoteTally>,
+}
+
+/// Sent before closing when the instance is draining. The client should
+/// connect to `url`, or to the same address again when it is empty.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Reconnect<'a> {
+    pub url: &'a str,
+}
+
+/// Reported by the client before authenticating. Clients older than the
+/// configured minimum version are closed with "upgrade required".
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ClientHello<'a> {
+    pub version: &'a str,
+}
+
+/// Looks up texts and frames containing all words of the query.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Search<'a> {
+    pub query: &'a str,
+}
+
+/// Texts have no object id, they are found by position only.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SearchHit {
+    pub object_id: Option<ObjectId>,
+    pub position: Position,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub enum Message<'a> {
+    #[serde(borrow)]
+    Auth(Auth<'a>),
+    Join(Join<'a>),
+    Create(Create<'a>),
+    BoardConfiguration(BoardConfiguration<'a>),
+    Step(Step),
+    Draw(Draw),
+    CursorMove(CursorMove),
+    Fill(Fill),
+    Image(Image<'a>),
+    Text(Text<'a>),
+    Undo(Undo),
+    Ping(Ping),
+    UserJoin(UserJoin<'a>),
+    UserLeave(UserLeave),
+    ServerMessage(ServerMessage<'a>),
+    History(History<'a>),
+    CreatePoll(CreatePoll<'a>),
+    Vote(Vote),
+    ClosePoll(ClosePoll),
+    PollResults(PollResults),
+    PlaceVote(PlaceVote),
+    Stamp(Stamp),
+    SetGrid(SetGrid),
+    SetBackground(SetBackground<'a>),
+    Connector(Connector),
+    CreateFrame(CreateFrame<'a>),
+    JumpToFrame(JumpToFrame),
+    Viewport(Viewport),
+    Minimap(Minimap<'a>),
+    RequestRegion(RequestRegion),
+    RegionPatch(RegionPatch<'a>),
+    CreateInvite(CreateInvite),
+    Invite(Invite<'a>),
+    JoinInvite(JoinInvite<'a>),
+    SetPrivate(SetPrivate),
+    RequestJoin(RequestJoin<'a>),
+    JoinRequest(JoinRequest<'a>),
+    RespondJoin(RespondJoin),
+    SetNotifications(SetNotifications),
+    SetPresence(SetPresence),
+    Typing(Typing),
+    TimeSync(TimeSync),
+    RequestSnapshot(RequestSnapshot),
+    SnapshotDelta(SnapshotDelta),
+    CreateViewToken(CreateViewToken),
+    ViewToken(ViewToken<'a>),
+    JoinView(JoinView<'a>),
+    Idempotent(Idempotent<'a>),
+    SetAccessibility(SetAccessibility),
+    AccessibilityEvent(AccessibilityEvent<'a>),
+    AckWindow(AckWindow),
+    Provisional(Provisional<'a>),
+    StepAssigned(StepAssigned),
+    BoardLock(BoardLock),
+    RequestExport(RequestExport),
+    ExportProgress(ExportProgress),
+    ExportReady(ExportReady<'a>),
+    Reconnect(Reconnect<'a>),
+    ClientHello(ClientHello<'a>),
+    Search(Search<'a>),
+    SearchResults(SearchResults),
+    RequestUserViewport(RequestUserViewport),
+    Follow(Follow),
+    CreateBookmark(CreateBookmark<'a>),
+    Mention(Mention<'a>),
+    MissedEvents(MissedEvents<'a>),
+    SetPalettePreset(SetPalettePreset),
+    CurveStroke(CurveStroke),
+    ClearRegion(ClearRegion),
+    ClearAll(ClearAll),
+    GuestIdentity(GuestIdentity<'a>),
+    Group(Group<'a>),
+    CopyObjects(CopyObjects),
+    Clipboard(Clipboard<'a>),
+    PasteObjects(PasteObjects<'a>),
+    CreateFromTemplate(CreateFromTemplate<'a>),
+    Portal(Portal<'a>),
+    EnterPortal(EnterPortal),
+    ReAuth(ReAuth<'a>),
+    ResumeToken(ResumeToken),
+    Resume(Resume<'a>),
+    UserProfile(UserProfile<'a>),
+    SetAccess(SetAccess<'a>),
+    RequestAccessList(RequestAccessList),
+    AccessList(AccessList<'a>),
+    AdminBroadcast(AdminBroadcast<'a>),
+    AdminDeleteBoard(AdminDeleteBoard<'a>),
+    RequestTimeline(RequestTimeline),
+    Timeline(Timeline<'a>),
+}
+
+/// Delivery guarantee of a message. Droppable messages are superseded by
+/// the next message of the same kind so losing some of them is harmless.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Reliability {
+    Reliable,
+    Droppable,
+}
+
+impl<'a> Message<'a> {
+    pub fn reliability(&self) -> Reliability {
+        match self {
+            Message::CursorMove(_) => Reliability::Droppable,
+            _ => Reliability::Reliable,
+        }
+    }
+
+    /// Whether the message changes board content and requires write access.
+    pub fn is_mutation(&self) -> bool {
+        match self {
+            Message::Draw(_) | Message::Fill(_) | Message::CurveStroke(_) | Message::Image(_) | Message::Text(_) | Message::Undo(_) |
+            Message::CreatePoll(_) | Message::ClosePoll(_) | Message::PlaceVote(_) | Message::Stamp(_) |
+            Message::SetGrid(_) | Message::SetBackground(_) | Message::Connector(_) | Message::CreateFrame(_) | Message::CreateBookmark(_) |
+            Message::SetPalettePreset(_) | Message::ClearRegion(_) | Message::ClearAll(_) | Message::Group(_) | Message::PasteObjects(_) | Message::Portal(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the message changes anything other members see, board content
+    /// as well as poll votes and cursors. Viewers may send none of them.
+    pub fn is_write(&self) -> bool {
+        self.is_mutation() || matches!(self, Message::Vote(_) | Message::CursorMove(_))
+    }
+
+    /// Whether the message makes the client enter a board.
+    pub fn is_join(&self) -> bool {
+        match self {
+            Message::Join(_) | Message::Create(_) | Message::CreateFromTemplate(_) | Message::JoinInvite(_) | Message::RequestJoin(_) | Message::JoinView(_) | Message::Resume(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck_macros::quickcheck;
+    use crate::messages::{Message, Auth, Join, Create, BoardConfiguration, PALETTE_SIZE, BoardFlags, Step, StepId, Draw, Position, Color, DrawFlags, CursorMove, UserId, Fill, Image, Text, Undo, Ping, UserJoin, UserLeave, ServerMessage, History, CreatePoll, PollId, Vote, ClosePoll, PollResults, PlaceVote, VoteTally, Stamp, ObjectId, StickerId, Grid, SetGrid, SetBackground, Connector, Bounds, CreateFrame, JumpToFrame, Viewport, Minimap, RequestRegion, RegionPatch, Role, CreateInvite, Invite, JoinInvite, SetPrivate, RequestJoin, JoinRequest, RespondJoin, NotificationFlags, SetNotifications, PresenceState, SetPresence, Typing, TimeSync, RequestSnapshot, SnapshotDelta, CreateViewToken, ViewToken, JoinView, Idempotent, SetAccessibility, AccessibilityEvent, AckWindow, Provisional, StepAssigned, LockState, BoardLock, RequestExport, ExportProgress, ExportReady, Reconnect, ClientHello, Search, SearchHit, SearchResults, RequestUserViewport, Follow, CreateBookmark, Mention, MissedEvents, SetPalettePreset, CurveStroke, CurveSegment, Brush, ClearRegion, ClearAll, GuestIdentity, Group, CopyObjects, Clipboard, PasteObjects, CreateFromTemplate, TemplateVariable, Portal, EnterPortal, ReAuth, ResumeToken, Resume, UserProfile, SetAccess, RequestAccessList, AccessEntry, AccessList, AdminBroadcast, AdminDeleteBoard, RequestTimeline, TimelineKind, TimelineEvent, Timeline};
+    use crate::ser::to_bytes;
+    use crate::de::{from_bytes, from_bytes_prefix};
+    use rand::Rng;
+
+
+    #[quickcheck]
+    fn test_auth(token: String) -> bool {
+        let message = Message::Auth(Auth {
+            jwt_token: token.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_join(name: String) -> bool {
+        let message = Message::Join(Join {
+            name: name.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_create(name: String, template_id: u64) -> bool {
+        let message = Message::Create(Create {
+            template_id,
+            name: name.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_board_configuration(history_size: u16, board_flags2: u8, background: u8, vote_quota: u8, stickers: Vec<String>, grid: Option<(u16, bool, bool)>, background_image: Option<String>, brushes: Vec<(u8, u8, bool)>) -> bool {
+        let mut rng = rand::thread_rng();
+        let mut palette = [0; PALETTE_SIZE];
+        palette.iter_mut().map(|x| *x = rng.gen());
+        let message = Message::BoardConfiguration(BoardConfiguration {
+            palette,
+            background,
+            board_flags: BoardFlags::from_bits_truncate(board_flags2),
+            history_size,
+            vote_quota,
+            stickers: stickers.iter().map(|x| x.as_str()).collect(),
+            grid: grid.map(|(cell_size, visible, snap)| Grid { cell_size, visible, snap }),
+            background_image: background_image.as_ref().map(|x| x.as_str()),
+            brushes: brushes.into_iter().map(|(radius, alpha, soft)| Brush { radius, alpha, soft }).collect(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_step(step_id: StepId) -> bool {
+        let message = Message::Step(Step {
+            step_id
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_draw(position: Position, color: Color, flags2: u8) -> bool {
+        let message = Message::Draw(Draw {
+            position,
+            color,
+            flags: DrawFlags(flags2),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_cursor_move(position: Position, user_id: UserId) -> bool {
+        let message = Message::CursorMove(CursorMove {
+            position,
+            user_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_fill(start: Position, end: Position, color: Color) -> bool {
+        let message = Message::Fill(Fill {
+            start,
+            end,
+            color,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_image(start: Position, end: Position, color: Color, url: String) -> bool {
+        let message = Message::Image(Image {
+            start,
+            end,
+            url: url.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_text(center: Position, text_color: Color, text: String) -> bool {
+        let message = Message::Text(Text {
+            center,
+            text: text.as_str(),
+            text_color,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_undo(last_actual_step_id: StepId) -> bool {
+        let message = Message::Undo(Undo {
+            last_actual_step_id
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_ping(timestamp: u64) -> bool {
+        let message = Message::Ping(Ping {
+            timestamp
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_user_join(user_id: UserId, username: String) -> bool {
+        let message = Message::UserJoin(UserJoin {
+            user_id,
+            username: username.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_user_leave(user_id: UserId) -> bool {
+        let message = Message::UserLeave(UserLeave {
+            user_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_server_message(message: String) -> bool {
+        let message = Message::ServerMessage(ServerMessage {
+            message: message.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_history(data: Vec<u8>) -> bool {
+        let message = Message::History(History {
+            data: data.as_slice()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_create_poll(poll_id: PollId, question: String, options: Vec<String>) -> bool {
+        let message = Message::CreatePoll(CreatePoll {
+            poll_id,
+            question: question.as_str(),
+            options: options.iter().map(|x| x.as_str()).collect(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_vote(poll_id: PollId, option: u8) -> bool {
+        let message = Message::Vote(Vote {
+            poll_id,
+            option,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_close_poll(poll_id: PollId) -> bool {
+        let message = Message::ClosePoll(ClosePoll {
+            poll_id
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_poll_results(poll_id: PollId, closed: bool, votes: Vec<u16>) -> bool {
+        let message = Message::PollResults(PollResults {
+            poll_id,
+            closed,
+            votes,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_place_vote(position: Position, user_id: UserId) -> bool {
+        let message = Message::PlaceVote(PlaceVote {
+            position,
+            user_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_stamp(object_id: ObjectId, position: Position, sticker_id: StickerId) -> bool {
+        let message = Message::Stamp(Stamp {
+            object_id,
+            position,
+            sticker_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_set_grid(grid: Option<(u16, bool, bool)>) -> bool {
+        let message = Message::SetGrid(SetGrid {
+            grid: grid.map(|(cell_size, visible, snap)| Grid { cell_size, visible, snap })
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_set_background(url: Option<String>) -> bool {
+        let message = Message::SetBackground(SetBackground {
+            url: url.as_ref().map(|x| x.as_str())
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_connector(object_id: ObjectId, from_object: ObjectId, to_object: ObjectId, style: u8) -> bool {
+        let message = Message::Connector(Connector {
+            object_id,
+            from_object,
+            to_object,
+            style,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_create_frame(object_id: ObjectId, start: Position, end: Position, title: String) -> bool {
+        let message = Message::CreateFrame(CreateFrame {
+            object_id,
+            bounds: Bounds { start, end },
+            title: title.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_jump_to_frame(object_id: ObjectId) -> bool {
+        let message = Message::JumpToFrame(JumpToFrame {
+            object_id
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_viewport(user_id: UserId, start: Position, end: Position) -> bool {
+        let message = Message::Viewport(Viewport {
+            user_id,
+            bounds: Bounds { start, end },
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_minimap(width: u16, height: u16, data: Vec<u8>, bookmarks: Vec<Position>) -> bool {
+        let message = Message::Minimap(Minimap {
+            width,
+            height,
+            data: data.as_slice(),
+            bookmarks,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_request_region(start: Position, end: Position) -> bool {
+        let message = Message::RequestRegion(RequestRegion {
+            bounds: Bounds { start, end }
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_region_patch(start: Position, end: Position, data: Vec<u8>) -> bool {
+        let message = Message::RegionPatch(RegionPatch {
+            bounds: Bounds { start, end },
+            data: data.as_slice(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_create_invite(role: u8, expires_in: u32, max_uses: u16) -> bool {
+        let message = Message::CreateInvite(CreateInvite {
+            role: Role::from_u8(role % 3).unwrap(),
+            expires_in,
+            max_uses,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_invite(token: String) -> bool {
+        let message = Message::Invite(Invite {
+            token: token.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_join_invite(token: String) -> bool {
+        let message = Message::JoinInvite(JoinInvite {
+            token: token.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_set_private(private: bool) -> bool {
+        let message = Message::SetPrivate(SetPrivate {
+            private
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_request_join(name: String) -> bool {
+        let message = Message::RequestJoin(RequestJoin {
+            name: name.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_join_request(request_id: u16, username: String) -> bool {
+        let message = Message::JoinRequest(JoinRequest {
+            request_id,
+            username: username.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_respond_join(request_id: u16, approve: bool) -> bool {
+        let message = Message::RespondJoin(RespondJoin {
+            request_id,
+            approve,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_set_notifications(flags2: u8) -> bool {
+        let message = Message::SetNotifications(SetNotifications {
+            flags: NotificationFlags::from_bits_truncate(flags2)
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_set_presence(user_id: UserId, state: u8) -> bool {
+        let states = [PresenceState::Active, PresenceState::Away, PresenceState::DoNotDisturb];
+        let message = Message::SetPresence(SetPresence {
+            user_id,
+            state: states[state as usize % states.len()],
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_typing(user_id: UserId, context: Option<ObjectId>, active: bool) -> bool {
+        let message = Message::Typing(Typing {
+            user_id,
+            context,
+            active,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_time_sync(client_send_time: u64, server_receive_time: u64, server_send_time: u64) -> bool {
+        let message = Message::TimeSync(TimeSync {
+            client_send_time,
+            server_receive_time,
+            server_send_time,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[test]
+    fn test_request_snapshot() {
+        let message = Message::RequestSnapshot(RequestSnapshot);
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        assert_eq!(message, deserialized);
+    }
+
+    #[quickcheck]
+    fn test_snapshot_delta(base_version: u32, version: u32, tiles: u16) -> bool {
+        let message = Message::SnapshotDelta(SnapshotDelta {
+            base_version,
+            version,
+            tiles,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_create_view_token(expires_in: u32) -> bool {
+        let message = Message::CreateViewToken(CreateViewToken {
+            expires_in
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_view_token(token: String) -> bool {
+        let message = Message::ViewToken(ViewToken {
+            token: token.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_join_view(token: String) -> bool {
+        let message = Message::JoinView(JoinView {
+            token: token.as_str()
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_length_limit_enforced(extra: u16) -> bool {
+        let name = "x".repeat((1 << 16) + extra as usize);
+        to_bytes(&Message::Join(Join { name: name.as_str() })).is_err()
+    }
+
+    #[quickcheck]
+    fn test_length_limit_boundary(missing: u16) -> bool {
+        let name = "x".repeat((1 << 16) - 1 - (missing as usize % 1024));
+        to_bytes(&Message::Join(Join { name: name.as_str() })).is_ok()
+    }
+
+    #[quickcheck]
+    fn test_decoded_reencodes_identically(data: Vec<u8>) -> bool {
+        match from_bytes_prefix::<Message>(data.as_slice()) {
+            Ok((message, length)) => to_bytes(&message).unwrap() == &data[..length],
+            Err(_) => true,
+        }
+    }
+
+    #[quickcheck]
+    fn test_concatenated_stream(positions: Vec<u32>, names: Vec<String>) -> bool {
+        let mut messages = vec![];
+        for (i, position) in positions.iter().enumerate() {
+            messages.push(Message::CursorMove(CursorMove { position: *position, user_id: i as u8 }));
+        }
+        for name in &names {
+            messages.push(Message::Join(Join { name: name.as_str() }));
+        }
+
+        let mut stream = vec![];
+        for message in &messages {
+            stream.extend(to_bytes(message).unwrap());
+        }
+
+        let mut rest = stream.as_slice();
+        let mut decoded = vec![];
+        while !rest.is_empty() {
+            let (message, length) = from_bytes_prefix::<Message>(rest).unwrap();
+            decoded.push(message);
+            rest = &rest[length..];
+        }
+        messages == decoded
+    }
+
+    #[quickcheck]
+    fn test_trailing_bytes_rejected(position: u32, user_id: UserId, trailing: Vec<u8>) -> bool {
+        let message = Message::CursorMove(CursorMove { position, user_id });
+        let mut serialized = to_bytes(&message).unwrap();
+        serialized.extend(&trailing);
+        from_bytes::<Message>(serialized.as_slice()).is_ok() == trailing.is_empty()
+    }
+
+    #[quickcheck]
+    fn test_idempotent(key: u32, message: Vec<u8>) -> bool {
+        let message = Message::Idempotent(Idempotent {
+            key,
+            message: message.as_slice(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_set_accessibility(enabled: bool) -> bool {
+        let message = Message::SetAccessibility(SetAccessibility {
+            enabled
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_accessibility_event(object_id: ObjectId, description: String) -> bool {
+        let message = Message::AccessibilityEvent(AccessibilityEvent {
+            object_id,
+            description: description.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_ack_window(sequence: u32) -> bool {
+        let message = Message::AckWindow(AckWindow {
+            sequence
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_provisional(provisional_id: u32, message: Vec<u8>) -> bool {
+        let message = Message::Provisional(Provisional {
+            provisional_id,
+            message: message.as_slice(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_step_assigned(provisional_id: u32, step_id: Option<StepId>) -> bool {
+        let message = Message::StepAssigned(StepAssigned {
+            provisional_id,
+            step_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_board_lock(state: Option<u8>, retry_after: u16) -> bool {
+        let states = [LockState::Compacting, LockState::Restoring, LockState::Migrating];
+        let message = Message::BoardLock(BoardLock {
+            state: state.map(|x| states[x as usize % states.len()]),
+            retry_after,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_request_export() -> bool {
+        let message = Message::RequestExport(RequestExport);
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_export_progress(job_id: u32, percent: u8) -> bool {
+        let message = Message::ExportProgress(ExportProgress {
+            job_id,
+            percent,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[quickcheck]
+    fn test_export_ready(job_id: u32, url: String, votes: Vec<(Position, u16)>) -> bool {
+        let message = Message::ExportReady(ExportReady {
+            job_id,
+            url: url.as_str(),
+            votes: votes.into_iter().map(|(position, votes)| VoteTally { position, votes }).collect(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct CursorMove {
-    pub position: Position,
-    pub user_id: UserId,
-}
+    #[quickcheck]
+    fn test_reconnect(url: String) -> bool {
+        let message = Message::Reconnect(Reconnect {
+            url: url.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct Fill {
-    pub start: Position,
-    pub end: Position,
-    pub color: Color,
-}
+    #[quickcheck]
+    fn test_client_hello(version: String) -> bool {
+        let message = Message::ClientHello(ClientHello {
+            version: version.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct Image<'a> {
-    pub start: Position,
-    pub end: Position,
-    pub url: &'a str,
-}
+    #[quickcheck]
+    fn test_search(query: String) -> bool {
+        let message = Message::Search(Search {
+            query: query.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct Text<'a> {
-    pub center: Position,
-    pub text: &'a str,
-    pub text_color: Color,
-}
+    #[quickcheck]
+    fn test_search_results(hits: Vec<(Option<ObjectId>, Position)>) -> bool {
+        let message = Message::SearchResults(SearchResults {
+            hits: hits.into_iter().map(|(object_id, position)| SearchHit { object_id, position }).collect(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct Undo {
-    pub last_actual_step_id: StepId
-}
+    #[quickcheck]
+    fn test_request_user_viewport(user_id: UserId) -> bool {
+        let message = Message::RequestUserViewport(RequestUserViewport {
+            user_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct Ping {
-    pub timestamp: u64
-}
+    #[quickcheck]
+    fn test_follow(user_id: Option<UserId>) -> bool {
+        let message = Message::Follow(Follow {
+            user_id,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct UserJoin<'a> {
-    pub user_id: UserId,
-    pub username: &'a str,
-}
+    #[quickcheck]
+    fn test_create_bookmark(object_id: ObjectId, position: Position, zoom: u16, label: String) -> bool {
+        let message = Message::CreateBookmark(CreateBookmark {
+            object_id,
+            position,
+            zoom,
+            label: label.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct UserLeave {
-    pub user_id: UserId
-}
+    #[quickcheck]
+    fn test_mention(user_id: UserId, position: Position, text: String) -> bool {
+        let message = Message::Mention(Mention {
+            user_id,
+            position,
+            text: text.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct ServerMessage<'a> {
-    pub message: &'a str
-}
+    #[quickcheck]
+    fn test_missed_events(data: Vec<u8>) -> bool {
+        let message = Message::MissedEvents(MissedEvents {
+            data: data.as_slice(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct History<'a> {
-    pub data: &'a [u8]
-}
+    #[quickcheck]
+    fn test_set_palette_preset(preset: u8) -> bool {
+        let message = Message::SetPalettePreset(SetPalettePreset {
+            preset,
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub enum Message<'a> {
-    #[serde(borrow)]
-    Auth(Auth<'a>),
-    Join(Join<'a>),
-    Create(Create<'a>),
-    BoardConfiguration(BoardConfiguration),
-    Step(Step),
-    Draw(Draw),
-    CursorMove(CursorMove),
-    Fill(Fill),
-    Image(Image<'a>),
-    Text(Text<'a>),
-    Undo(Undo),
-    Ping(Ping),
-    UserJoin(UserJoin<'a>),
-    UserLeave(UserLeave),
-    ServerMessage(ServerMessage<'a>),
-    History(History<'a>),
-}
+    #[quickcheck]
+    fn test_curve_stroke(color: Color, start: Position, segments: Vec<(Position, Position, Position)>) -> bool {
+        let message = Message::CurveStroke(CurveStroke {
+            color,
+            start,
+            segments: segments.into_iter().map(|(control1, control2, end)| CurveSegment { control1, control2, end }).collect(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
-#[cfg(test)]
-mod test {
-    use quickcheck_macros::quickcheck;
-    use crate::messages::{Message, Auth, Join, Create, BoardConfiguration, PALETTE_SIZE, BoardFlags, Step, StepId, Draw, Position, Color, DrawFlags, CursorMove, UserId, Fill, Image, Text, Undo, Ping, UserJoin, UserLeave, ServerMessage, History};
-    use crate::ser::to_bytes;
-    use crate::de::from_bytes;
-    use rand::Rng;
+    #[quickcheck]
+    fn test_clear_region(start: Position, end: Position) -> bool {
+        let message = Message::ClearRegion(ClearRegion {
+            bounds: Bounds { start, end },
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
 
+    #[test]
+    fn test_clear_all() {
+        let message = Message::ClearAll(ClearAll);
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        assert_eq!(message, deserialized);
+    }
 
     #[quickcheck]
-    fn test_auth(token: String) -> bool {
-        let message = Message::Auth(Auth {
-            jwt_token: token.as_str()
+    fn test_guest_identity(username: String) -> bool {
+        let message = Message::GuestIdentity(GuestIdentity {
+            username: username.as_str(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -173,9 +1780,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_join(name: String) -> bool {
-        let message = Message::Join(Join {
-            name: name.as_str()
+    fn test_group(data: Vec<u8>) -> bool {
+        let message = Message::Group(Group {
+            data: data.as_slice(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -183,10 +1790,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_create(name: String, template_id: u64) -> bool {
-        let message = Message::Create(Create {
-            template_id,
-            name: name.as_str(),
+    fn test_copy_objects(ids: Vec<ObjectId>) -> bool {
+        let message = Message::CopyObjects(CopyObjects {
+            ids,
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -194,15 +1800,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_board_configuration(history_size: u16, board_flags2: u8, background: u8) -> bool {
-        let mut rng = rand::thread_rng();
-        let mut palette = [0; PALETTE_SIZE];
-        palette.iter_mut().map(|x| *x = rng.gen());
-        let message = Message::BoardConfiguration(BoardConfiguration {
-            palette,
-            background,
-            board_flags: BoardFlags::from_bits_truncate(board_flags2),
-            history_size,
+    fn test_clipboard(blob: Vec<u8>) -> bool {
+        let message = Message::Clipboard(Clipboard {
+            blob: blob.as_slice(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -210,9 +1810,11 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_step(step_id: StepId) -> bool {
-        let message = Message::Step(Step {
-            step_id
+    fn test_paste_objects(blob: Vec<u8>, offset_x: i16, offset_y: i16) -> bool {
+        let message = Message::PasteObjects(PasteObjects {
+            blob: blob.as_slice(),
+            offset_x,
+            offset_y,
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -220,11 +1822,11 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_draw(position: Position, color: Color, flags2: u8) -> bool {
-        let message = Message::Draw(Draw {
-            position,
-            color,
-            flags: DrawFlags(flags2),
+    fn test_create_from_template(template_id: u64, name: String, variables: Vec<(String, String)>) -> bool {
+        let message = Message::CreateFromTemplate(CreateFromTemplate {
+            template_id,
+            name: name.as_str(),
+            variables: variables.iter().map(|(name, value)| TemplateVariable { name: name.as_str(), value: value.as_str() }).collect(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -232,10 +1834,11 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_cursor_move(position: Position, user_id: UserId) -> bool {
-        let message = Message::CursorMove(CursorMove {
+    fn test_portal(object_id: ObjectId, position: Position, target_board: String) -> bool {
+        let message = Message::Portal(Portal {
+            object_id,
             position,
-            user_id,
+            target_board: target_board.as_str(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -243,11 +1846,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_fill(start: Position, end: Position, color: Color) -> bool {
-        let message = Message::Fill(Fill {
-            start,
-            end,
-            color,
+    fn test_enter_portal(object_id: ObjectId) -> bool {
+        let message = Message::EnterPortal(EnterPortal {
+            object_id,
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -255,11 +1856,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_image(start: Position, end: Position, color: Color, url: String) -> bool {
-        let message = Message::Image(Image {
-            start,
-            end,
-            url: url.as_str(),
+    fn test_reauth(jwt_token: String) -> bool {
+        let message = Message::ReAuth(ReAuth {
+            jwt_token: jwt_token.as_str(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -267,11 +1866,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_text(center: Position, text_color: Color, text: String) -> bool {
-        let message = Message::Text(Text {
-            center,
-            text: text.as_str(),
-            text_color,
+    fn test_resume_token(token: u64) -> bool {
+        let message = Message::ResumeToken(ResumeToken {
+            token,
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -279,9 +1876,10 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_undo(last_actual_step_id: StepId) -> bool {
-        let message = Message::Undo(Undo {
-            last_actual_step_id
+    fn test_resume(board_name: String, token: u64) -> bool {
+        let message = Message::Resume(Resume {
+            board_name: board_name.as_str(),
+            token,
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -289,9 +1887,11 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_ping(timestamp: u64) -> bool {
-        let message = Message::Ping(Ping {
-            timestamp
+    fn test_user_profile(user_id: UserId, display_name: String, avatar_url: String) -> bool {
+        let message = Message::UserProfile(UserProfile {
+            user_id,
+            display_name: display_name.as_str(),
+            avatar_url: avatar_url.as_str(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -299,20 +1899,28 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_user_join(user_id: UserId, username: String) -> bool {
-        let message = Message::UserJoin(UserJoin {
-            user_id,
+    fn test_set_access(username: String, role: Option<u8>) -> bool {
+        let message = Message::SetAccess(SetAccess {
             username: username.as_str(),
+            role: role.map(|x| Role::from_u8(x % 3).unwrap()),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
         return message == deserialized;
     }
 
+    #[test]
+    fn test_request_access_list() {
+        let message = Message::RequestAccessList(RequestAccessList);
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        assert_eq!(message, deserialized);
+    }
+
     #[quickcheck]
-    fn test_user_leave(user_id: UserId) -> bool {
-        let message = Message::UserLeave(UserLeave {
-            user_id,
+    fn test_access_list(entries: Vec<(String, u8)>) -> bool {
+        let message = Message::AccessList(AccessList {
+            entries: entries.iter().map(|(username, role)| AccessEntry { username: username.as_str(), role: Role::from_u8(role % 3).unwrap() }).collect(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -320,9 +1928,9 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_server_message(message: String) -> bool {
-        let message = Message::ServerMessage(ServerMessage {
-            message: message.as_str()
+    fn test_admin_broadcast(message: String) -> bool {
+        let message = Message::AdminBroadcast(AdminBroadcast {
+            message: message.as_str(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -330,9 +1938,33 @@ mod test {
     }
 
     #[quickcheck]
-    fn test_history(data: Vec<u8>) -> bool {
-        let message = Message::History(History {
-            data: data.as_slice()
+    fn test_admin_delete_board(name: String) -> bool {
+        let message = Message::AdminDeleteBoard(AdminDeleteBoard {
+            name: name.as_str(),
+        });
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        return message == deserialized;
+    }
+
+    #[test]
+    fn test_request_timeline() {
+        let message = Message::RequestTimeline(RequestTimeline);
+        let serialized = to_bytes(&message).unwrap();
+        let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
+        assert_eq!(message, deserialized);
+    }
+
+    #[quickcheck]
+    fn test_timeline(events: Vec<(u64, String, String, u16)>) -> bool {
+        let message = Message::Timeline(Timeline {
+            events: events.iter().map(|(time, username, detail, count)| TimelineEvent {
+                time: *time,
+                kind: TimelineKind::Join,
+                username: username.as_str(),
+                detail: detail.as_str(),
+                count: *count,
+            }).collect(),
         });
         let serialized = to_bytes(&message).unwrap();
         let deserialized: Message = from_bytes(serialized.as_slice()).unwrap();
@@ -0,0 +1,60 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Shortcodes replaced by emoji, matching the default stickers and the
+/// most used reactions.
+const SHORTCODES: [(&str, &str); 12] = [
+    (":thumbsup:", "\u{1f44d}"),
+    (":+1:", "\u{1f44d}"),
+    (":thumbsdown:", "\u{1f44e}"),
+    (":-1:", "\u{1f44e}"),
+    (":heart:", "\u{2764}\u{fe0f}"),
+    (":smile:", "\u{1f604}"),
+    (":laughing:", "\u{1f606}"),
+    (":star:", "\u{2b50}"),
+    (":fire:", "\u{1f525}"),
+    (":tada:", "\u{1f389}"),
+    (":question:", "\u{2753}"),
+    (":check:", "\u{2705}"),
+];
+
+/// Normalizes user-written text before it is stored or broadcast so all
+/// clients render it the same: replaces emoji shortcodes, strips control
+/// characters except line breaks and tabs and composes to NFC.
+pub fn text(text: &str) -> String {
+    let mut result: String = text.chars()
+        .filter(|x| !x.is_control() || *x == '\n' || *x == '\t')
+        .nfc()
+        .collect();
+
+    if result.contains(':') {
+        for (shortcode, emoji) in SHORTCODES.iter() {
+            result = result.replace(shortcode, emoji);
+        }
+    }
+    result
+}
+
+/// Usernames are single line and never contain emoji shortcodes.
+pub fn username(username: &str) -> String {
+    username.chars()
+        .filter(|x| !x.is_control())
+        .nfc()
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_text() {
+        assert_eq!(super::text("nice :thumbsup:\u{7}"), "nice \u{1f44d}");
+        assert_eq!(super::text("cafe\u{301}\nok"), "caf\u{e9}\nok");
+        assert_eq!(super::text("10:30 :unknown:"), "10:30 :unknown:");
+    }
+
+    #[test]
+    fn test_username() {
+        assert_eq!(super::username(" Jos\u{65}\u{301}\r\n"), "Jos\u{e9}");
+    }
+}
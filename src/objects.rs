@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::num::Wrapping;
+use crate::messages::{ObjectId, Position, StickerId, Bounds};
+use crate::canvas::area;
+
+/// Board content which is addressable by its id.
+pub enum BoardObject {
+    Stamp { position: Position, sticker_id: StickerId },
+    Connector { from_object: ObjectId, to_object: ObjectId, style: u8 },
+    Frame { bounds: Bounds, title: String },
+    Bookmark { position: Position, zoom: u16, label: String },
+    Portal { position: Position, target_board: String },
+}
+
+/// Registry of all objects on a single board.
+pub struct ObjectRegistry {
+    objects: HashMap<ObjectId, BoardObject>,
+    last_object_id: Wrapping<ObjectId>,
+}
+
+impl ObjectRegistry {
+    pub fn new() -> Self {
+        ObjectRegistry {
+            objects: HashMap::new(),
+            last_object_id: Wrapping(0),
+        }
+    }
+
+    pub fn insert(&mut self, object: BoardObject) -> ObjectId {
+        let object_id = self.last_object_id.0;
+        self.last_object_id += Wrapping(1);
+        self.objects.insert(object_id, object);
+        object_id
+    }
+
+    pub fn contains(&self, object_id: ObjectId) -> bool {
+        self.objects.contains_key(&object_id)
+    }
+
+    pub fn get(&self, object_id: ObjectId) -> Option<&BoardObject> {
+        self.objects.get(&object_id)
+    }
+
+    /// Ids of all objects in the order they were created.
+    pub fn ids(&self) -> Vec<ObjectId> {
+        let mut ids: Vec<ObjectId> = self.objects.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Describes the object in words, e.g. `heart sticker at top-left`.
+    pub fn describe(&self, object_id: ObjectId, stickers: &[String]) -> Option<String> {
+        match self.get(object_id)? {
+            BoardObject::Stamp { position, .. } => Some(format!("{} at {}", self.name(object_id, stickers)?, area(*position))),
+            BoardObject::Connector { from_object, to_object, .. } => Some(format!(
+                "connector from {} to {}",
+                self.name(*from_object, stickers).unwrap_or_else(|| "removed object".to_string()),
+                self.name(*to_object, stickers).unwrap_or_else(|| "removed object".to_string()),
+            )),
+            BoardObject::Frame { bounds, .. } => Some(format!("{} at {}", self.name(object_id, stickers)?, area(bounds.start))),
+            BoardObject::Bookmark { position, .. } => Some(format!("{} at {}", self.name(object_id, stickers)?, area(*position))),
+            BoardObject::Portal { position, .. } => Some(format!("{} at {}", self.name(object_id, stickers)?, area(*position))),
+        }
+    }
+
+    fn name(&self, object_id: ObjectId, stickers: &[String]) -> Option<String> {
+        match self.get(object_id)? {
+            BoardObject::Stamp { sticker_id, .. } => {
+                /* stickers are paths like /stickers/thumbs-up.svg */
+                let path = stickers.get(*sticker_id as usize).map(|x| x.as_str()).unwrap_or("");
+                let file = path.rsplit('/').next().unwrap_or("");
+                let name = file.split('.').next().unwrap_or("").replace('-', " ");
+                Some(format!("{} sticker", name).trim_start().to_string())
+            }
+            BoardObject::Connector { .. } => Some("connector".to_string()),
+            BoardObject::Frame { title, .. } => Some(format!("frame \"{}\"", title)),
+            BoardObject::Bookmark { label, .. } => Some(format!("bookmark \"{}\"", label)),
+            BoardObject::Portal { target_board, .. } => Some(format!("portal to \"{}\"", target_board)),
+        }
+    }
+}
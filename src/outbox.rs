@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use ws::{CloseCode, Result};
+use ws::util::Token;
+use crate::transport::{Transport, Out};
+
+/// Timeout token used to drain queued frames.
+pub const FLUSH: Token = Token(5);
+
+/// Frames of queued classes sent per flush, other connections are served
+/// by the event loop in between.
+const FLUSH_BATCH: usize = 16;
+
+/// Only the most recent cursor moves are worth delivering.
+const MAX_QUEUED_CURSORS: usize = 32;
+
+/// Priority class of an outgoing frame, most urgent first. Control
+/// messages and draws share the live class as they must stay in order,
+/// they also wait for backfill queued before them.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
+pub enum Priority {
+    Live,
+    Backfill,
+    Cursor,
+}
+
+#[derive(Default)]
+struct Queues {
+    /// History followed by live frames sent after it.
+    backfill: VecDeque<Vec<u8>>,
+    cursors: VecDeque<Vec<u8>>,
+}
+
+impl Queues {
+    fn is_empty(&self) -> bool {
+        self.backfill.is_empty() && self.cursors.is_empty()
+    }
+}
+
+/// Per-client outgoing queue. Control messages and live draws are sent
+/// right away unless history backfill is pending, history and cursors wait
+/// in queues drained in small batches so a joiner's history never holds up
+/// live traffic of others.
+pub struct Outbox {
+    inner: Out,
+    queues: Mutex<Queues>,
+    scheduled: AtomicBool,
+}
+
+impl Outbox {
+    pub fn new(inner: Out) -> Self {
+        Outbox {
+            inner,
+            queues: Mutex::new(Queues::default()),
+            scheduled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn send_as(&self, frame: Vec<u8>, priority: Priority) -> Result<()> {
+        let mut queues = self.queues.lock().unwrap();
+        match priority {
+            Priority::Live if queues.backfill.is_empty() => return self.inner.send(frame),
+            Priority::Live | Priority::Backfill => queues.backfill.push_back(frame),
+            Priority::Cursor => {
+                if queues.cursors.len() == MAX_QUEUED_CURSORS {
+                    queues.cursors.pop_front();
+                }
+                queues.cursors.push_back(frame);
+            }
+        }
+
+        self.schedule()
+    }
+
+    /// Sends the next batch of queued frames, called on the `FLUSH` timeout.
+    pub fn flush(&self) -> Result<()> {
+        let mut queues = self.queues.lock().unwrap();
+        self.scheduled.store(false, Ordering::SeqCst);
+
+        let mut budget = FLUSH_BATCH;
+        while budget > 0 {
+            match queues.backfill.pop_front() {
+                Some(t) => self.inner.send(t)?,
+                None => break,
+            }
+            budget -= 1;
+        }
+        while budget > 0 {
+            match queues.cursors.pop_front() {
+                /* lost cursor moves are superseded by later ones */
+                Some(t) => { let _ = self.inner.send_droppable(t); }
+                None => break,
+            }
+            budget -= 1;
+        }
+
+        if queues.is_empty() {
+            return Ok(());
+        }
+        self.schedule()
+    }
+
+    fn schedule(&self) -> Result<()> {
+        if self.scheduled.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.inner.timeout(0, FLUSH)
+    }
+}
+
+impl Transport for Outbox {
+    fn send(&self, frame: Vec<u8>) -> Result<()> {
+        self.send_as(frame, Priority::Live)
+    }
+
+    fn send_droppable(&self, frame: Vec<u8>) -> Result<()> {
+        self.send_as(frame, Priority::Cursor)
+    }
+
+    fn send_backfill(&self, frame: Vec<u8>) -> Result<()> {
+        self.send_as(frame, Priority::Backfill)
+    }
+
+    fn close(&self, code: CloseCode, reason: String) -> Result<()> {
+        self.inner.close(code, reason)
+    }
+
+    fn timeout(&self, ms: u64, token: Token) -> Result<()> {
+        self.inner.timeout(ms, token)
+    }
+
+    fn peer(&self) -> String {
+        self.inner.peer()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use crate::transport::MemoryTransport;
+    use super::{Outbox, Priority, FLUSH, FLUSH_BATCH};
+
+    #[test]
+    fn test_draws_stay_behind_backfill() {
+        let transport = Arc::new(MemoryTransport::default());
+        let outbox = Outbox::new(transport.clone());
+        outbox.send_as(vec![0xfe], Priority::Live).unwrap();
+        for i in 0..FLUSH_BATCH + 1 {
+            outbox.send_as(vec![i as u8], Priority::Backfill).unwrap();
+        }
+        outbox.send_as(vec![0xff], Priority::Live).unwrap();
+        assert_eq!(*transport.sent.lock().unwrap(), vec![vec![0xfe]]);
+        assert_eq!(*transport.timeouts.lock().unwrap(), vec![(0, FLUSH)]);
+
+        outbox.flush().unwrap();
+        assert_eq!(transport.sent.lock().unwrap().len(), FLUSH_BATCH + 1);
+        outbox.flush().unwrap();
+        let sent = transport.sent.lock().unwrap();
+        assert_eq!(sent.len(), FLUSH_BATCH + 3);
+        assert_eq!(sent[FLUSH_BATCH + 1], vec![FLUSH_BATCH as u8]);
+        assert_eq!(sent[FLUSH_BATCH + 2], vec![0xff]);
+        assert_eq!(transport.timeouts.lock().unwrap().len(), 2);
+    }
+}
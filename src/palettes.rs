@@ -0,0 +1,52 @@
+use crate::messages::{Palette, PALETTE_DEFAULT, color};
+
+/// Curated palettes selectable by their index. Every preset has exactly
+/// `PALETTE_SIZE` colors so drawn color indices stay valid when switching.
+pub const PRESETS: [(&str, Palette); 4] = [
+    ("default", PALETTE_DEFAULT),
+    /* Okabe & Ito, distinguishable with all common color vision deficiencies */
+    ("okabe-ito", [
+        color(0, 0, 0),
+        color(230, 159, 0),
+        color(86, 180, 233),
+        color(0, 158, 115),
+        color(240, 228, 66),
+        color(0, 114, 178),
+        color(213, 94, 0),
+        color(204, 121, 167),
+    ]),
+    /* Paul Tol's bright scheme, color-blind safe */
+    ("tol-bright", [
+        color(0, 0, 0),
+        color(68, 119, 170),
+        color(238, 102, 119),
+        color(34, 136, 51),
+        color(204, 187, 68),
+        color(102, 204, 238),
+        color(170, 51, 119),
+        color(255, 255, 255),
+    ]),
+    ("grayscale", [
+        color(0, 0, 0),
+        color(36, 36, 36),
+        color(73, 73, 73),
+        color(109, 109, 109),
+        color(146, 146, 146),
+        color(182, 182, 182),
+        color(219, 219, 219),
+        color(255, 255, 255),
+    ]),
+];
+
+pub fn preset(preset: u8) -> Option<Palette> {
+    PRESETS.get(preset as usize).map(|(_, palette)| *palette)
+}
+
+/// Templates with id of a preset start with its palette, others with the
+/// default one.
+pub fn for_template(template_id: u64) -> Palette {
+    if template_id < PRESETS.len() as u64 {
+        return PRESETS[template_id as usize].1;
+    }
+    PALETTE_DEFAULT
+}
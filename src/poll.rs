@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use crate::messages::{Message, PollId, CreatePoll, PollResults};
+use crate::ser::to_bytes;
+use crate::error::Error;
+
+pub const MAX_POLL_OPTIONS: usize = 32;
+
+/// Poll running on a board. Votes are tracked per username so a user
+/// connected multiple times still gets only one vote.
+pub struct Poll {
+    pub creator: String,
+    pub question: String,
+    pub options: Vec<String>,
+    pub closed: bool,
+    votes: HashMap<String, u8>,
+}
+
+impl Poll {
+    pub fn new(creator: &str, t: &CreatePoll) -> Result<Self, Error> {
+        if t.options.is_empty() || t.options.len() > MAX_POLL_OPTIONS {
+            return Err(Error::Message("invalid number of poll options".to_string()));
+        }
+
+        Ok(Poll {
+            creator: creator.to_string(),
+            question: t.question.to_string(),
+            options: t.options.iter().map(|x| x.to_string()).collect(),
+            closed: false,
+            votes: HashMap::new(),
+        })
+    }
+
+    pub fn vote(&mut self, username: &str, option: u8) -> Result<(), Error> {
+        if self.closed {
+            return Err(Error::Message("poll is closed".to_string()));
+        }
+
+        if option as usize >= self.options.len() {
+            return Err(Error::Message("poll option out of range".to_string()));
+        }
+
+        if self.votes.contains_key(username) {
+            return Err(Error::Message("user already voted".to_string()));
+        }
+
+        self.votes.insert(username.to_string(), option);
+        Ok(())
+    }
+
+    pub fn close(&mut self, username: &str) -> Result<(), Error> {
+        self.check_close(username)?;
+        self.closed = true;
+        Ok(())
+    }
+
+    pub fn check_close(&self, username: &str) -> Result<(), Error> {
+        if self.creator != username {
+            return Err(Error::Message("only creator can close the poll".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn tally(&self) -> Vec<u16> {
+        let mut votes = vec![0u16; self.options.len()];
+        for option in self.votes.values() {
+            votes[*option as usize] = votes[*option as usize].saturating_add(1);
+        }
+        votes
+    }
+
+    pub fn create_message(&self, poll_id: PollId) -> Vec<u8> {
+        to_bytes(&Message::CreatePoll(CreatePoll {
+            poll_id,
+            question: self.question.as_str(),
+            options: self.options.iter().map(|x| x.as_str()).collect(),
+        })).unwrap()
+    }
+
+    pub fn results_message(&self, poll_id: PollId) -> Vec<u8> {
+        to_bytes(&Message::PollResults(PollResults {
+            poll_id,
+            closed: self.closed,
+            votes: self.tally(),
+        })).unwrap()
+    }
+}
@@ -0,0 +1,119 @@
+//! Display names and avatars of users resolved from an external profile
+//! service by the user id of their token, which keeps tokens small.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Profile {
+    pub display_name: String,
+    pub avatar_url: String,
+}
+
+/// Source of profiles, the profile service in production.
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, user_id: &str) -> Result<Profile, String>;
+}
+
+/// Fetches `{user_id}` in the url replaced by the user id and expects
+/// a JSON object with `display_name` and `avatar_url`.
+pub struct HttpResolver {
+    url: String,
+}
+
+impl HttpResolver {
+    pub fn new(url: String) -> Self {
+        HttpResolver { url }
+    }
+}
+
+impl Resolver for HttpResolver {
+    fn resolve(&self, user_id: &str) -> Result<Profile, String> {
+        let url = self.url.replace("{user_id}", &urlencode(user_id));
+        let body = ureq::get(&url)
+            .timeout(FETCH_TIMEOUT)
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())?;
+        serde_json::from_str(&body).map_err(|e| e.to_string())
+    }
+}
+
+pub fn urlencode(value: &str) -> String {
+    value.bytes().map(|x| match x {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (x as char).to_string(),
+        _ => format!("%{:02X}", x),
+    }).collect()
+}
+
+/// Profiles shared by all connections. Failed lookups are cached as well
+/// so an unavailable service is not asked on every join.
+pub struct ProfileCache {
+    resolver: Arc<dyn Resolver>,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Option<Profile>, Instant)>>,
+}
+
+impl ProfileCache {
+    pub fn new(resolver: Arc<dyn Resolver>, ttl: Duration) -> Self {
+        ProfileCache {
+            resolver,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Cached result of the last lookup, `None` when it has to be resolved.
+    pub fn cached(&self, user_id: &str) -> Option<Option<Profile>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, at)| at.elapsed() < self.ttl);
+        entries.get(user_id).map(|(profile, _)| profile.clone())
+    }
+
+    /// Asks the resolver, blocking until it answers.
+    pub fn resolve(&self, user_id: &str) -> Result<Profile, String> {
+        let result = self.resolver.resolve(user_id);
+        self.entries.lock().unwrap().insert(user_id.to_string(), (result.clone().ok(), Instant::now()));
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use super::{Profile, ProfileCache, Resolver, urlencode};
+
+    struct Counting(AtomicUsize);
+
+    impl Resolver for Counting {
+        fn resolve(&self, user_id: &str) -> Result<Profile, String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            match user_id {
+                "42" => Ok(Profile { display_name: "Alice".to_string(), avatar_url: "https://example.com/a.png".to_string() }),
+                _ => Err("not found".to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_profile_cache() {
+        let resolver = Arc::new(Counting(AtomicUsize::new(0)));
+        let cache = ProfileCache::new(resolver.clone(), Duration::from_secs(60));
+        assert_eq!(cache.cached("42"), None);
+
+        assert_eq!(cache.resolve("42").unwrap().display_name, "Alice");
+        assert!(cache.resolve("7").is_err());
+        assert_eq!(cache.cached("42").unwrap().unwrap().display_name, "Alice");
+        assert_eq!(cache.cached("7"), Some(None));
+        assert_eq!(resolver.0.load(Ordering::SeqCst), 2);
+
+        assert_eq!(urlencode("a b/c"), "a%20b%2Fc");
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::de::from_bytes;
+use crate::messages::Message;
+use crate::request::RequestId;
+
+/// Stores the frame which could not be handled into the quarantine
+/// directory as `<request id>.bin` along with a human readable
+/// `<request id>.txt` describing where it came from.
+pub fn capture(dir: &Path, id: RequestId, username: &str, board: Option<&str>, frame: &[u8], reason: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(format!("{}.bin", id)), frame)?;
+
+    let mut report = format!("request: {}\nsender: {}\nboard: {}\nreason: {}\n\n", id, username, board.unwrap_or("-"), reason);
+    for (i, line) in frame.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|x| format!("{:02x}", x)).collect();
+        report.push_str(&format!("{:08x}  {}\n", i * 16, hex.join(" ")));
+    }
+    fs::write(dir.join(format!("{}.txt", id)), report)
+}
+
+/// Lists request ids of quarantined frames.
+pub fn list(dir: &Path) -> io::Result<Vec<String>> {
+    let mut ids = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map(|x| x == "bin").unwrap_or(false) {
+            if let Some(id) = path.file_stem().and_then(|x| x.to_str()) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Decodes the quarantined frame again and returns the report followed by
+/// the debug representation of the decoding result.
+pub fn replay(dir: &Path, id: &str) -> io::Result<String> {
+    if id.is_empty() || !id.chars().all(|x| x.is_ascii_hexdigit() || x == '-') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid request id"));
+    }
+
+    let frame = fs::read(dir.join(format!("{}.bin", id)))?;
+    let report = fs::read_to_string(dir.join(format!("{}.txt", id))).unwrap_or_default();
+    let decoded = match from_bytes::<Message>(frame.as_slice()) {
+        Ok(msg) => format!("{:#?}", msg),
+        Err(e) => format!("decoding failed: {:?}", e),
+    };
+    Ok(format!("{}\n{}\n", report, decoded))
+}
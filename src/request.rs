@@ -0,0 +1,44 @@
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+static LAST_CONNECTION_ID: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    static CURRENT: Cell<Option<RequestId>> = Cell::new(None);
+    static RECEIVED: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// Identifies an inbound message by its connection and position within
+/// the connection. Formatted as `connection-message`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RequestId {
+    pub connection: u32,
+    pub message: u32,
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:08x}-{}", self.connection, self.message)
+    }
+}
+
+pub fn next_connection_id() -> u32 {
+    LAST_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Request being handled on this thread, included in every log line.
+pub fn current() -> Option<RequestId> {
+    CURRENT.with(|x| x.get())
+}
+
+pub fn set_current(id: Option<RequestId>) {
+    CURRENT.with(|x| x.set(id));
+    RECEIVED.with(|x| x.set(id.map(|_| Instant::now())));
+}
+
+/// Time since the request being handled on this thread was received.
+pub fn elapsed() -> Option<Duration> {
+    RECEIVED.with(|x| x.get()).map(|x| x.elapsed())
+}
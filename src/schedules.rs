@@ -0,0 +1,140 @@
+//! Boards created automatically from a template on a cron-like schedule,
+//! e.g. a weekly retrospective named by its date.
+
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::clock;
+use crate::templates;
+
+/// Runs are searched at most this many days ahead.
+const MAX_LOOKAHEAD_DAYS: u64 = 366;
+
+/// Five cron fields as bit sets: minute, hour, day of month, month and
+/// day of week (0 is Sunday). Unlike cron both day fields have to match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cron {
+    fields: [u64; 5],
+}
+
+const RANGES: [(u32, u32); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 6)];
+
+impl Cron {
+    /// Fields accept `*`, numbers, ranges `a-b` and steps `*/n` or
+    /// `a-b/n` separated by commas.
+    pub fn parse(expression: &str) -> Result<Cron, String> {
+        let parts: Vec<&str> = expression.split_whitespace().collect();
+        if parts.len() != 5 {
+            return Err("cron expression must have five fields".to_string());
+        }
+
+        let mut fields = [0; 5];
+        for (i, part) in parts.iter().enumerate() {
+            let (min, max) = RANGES[i];
+            for item in part.split(',') {
+                let (range, step) = match item.split_once('/') {
+                    Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("invalid step in {}", item))?),
+                    None => (item, 1),
+                };
+                let (from, to) = match range {
+                    "*" => (min, max),
+                    _ => match range.split_once('-') {
+                        Some((from, to)) => (number(from)?, number(to)?),
+                        None => (number(range)?, number(range)?),
+                    },
+                };
+                if step == 0 || from < min || to > max || from > to {
+                    return Err(format!("{} is out of range {}-{}", item, min, max));
+                }
+                for value in (from..=to).step_by(step as usize) {
+                    fields[i] |= 1 << value;
+                }
+            }
+        }
+        Ok(Cron { fields })
+    }
+
+    fn matches(&self, field: usize, value: u32) -> bool {
+        self.fields[field] & (1 << value) != 0
+    }
+
+    /// First matching minute after the unix time.
+    pub fn next_after(&self, unix: u64) -> Option<u64> {
+        let start = unix / 60 * 60 + 60;
+        let first_day = start / 86400;
+        for day in first_day..first_day + MAX_LOOKAHEAD_DAYS {
+            let (_, month, day_of_month) = clock::civil(day * 86400);
+            let weekday = ((day + 4) % 7) as u32;
+            if !self.matches(2, day_of_month) || !self.matches(3, month) || !self.matches(4, weekday) {
+                continue;
+            }
+
+            for minute_of_day in 0..24 * 60 {
+                let time = day * 86400 + minute_of_day * 60;
+                if time >= start && self.matches(1, (minute_of_day / 60) as u32) && self.matches(0, (minute_of_day % 60) as u32) {
+                    return Some(time);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn number(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("{} is not a number", value))
+}
+
+/// Boards named by `name` are created from the template whenever the cron
+/// expression matches. The name may contain placeholders like `{{date}}`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Schedule {
+    pub cron: String,
+    pub template_id: u64,
+    pub name: String,
+    pub owner: String,
+    pub plan: String,
+    pub next_run: Option<u64>,
+    #[serde(skip)]
+    pub expression: Cron,
+}
+
+impl Schedule {
+    pub fn new(cron: &str, template_id: u64, name: &str, owner: &str, plan: &str) -> Result<Schedule, String> {
+        let expression = Cron::parse(cron)?;
+        Ok(Schedule {
+            cron: cron.to_string(),
+            template_id,
+            name: name.to_string(),
+            owner: owner.to_string(),
+            plan: plan.to_string(),
+            next_run: expression.next_after(clock::now()),
+            expression,
+        })
+    }
+
+    /// Board name of the run at the unix time.
+    pub fn board_name(&self, time: u64) -> String {
+        let mut variables = HashMap::new();
+        variables.insert("date".to_string(), templates::date(time));
+        templates::substitute(&self.name, &variables)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Cron;
+
+    #[test]
+    fn test_next_after() {
+        /* 2023-11-14 22:13:20 UTC, a Tuesday, next Monday 9:00 is 2023-11-20 */
+        let now = 1_700_000_000;
+        let weekly = Cron::parse("0 9 * * 1").unwrap();
+        assert_eq!(weekly.next_after(now), Some(1_700_470_800));
+
+        let every_quarter = Cron::parse("*/15 * * * *").unwrap();
+        assert_eq!(every_quarter.next_after(now), Some(1_700_000_100));
+
+        assert!(Cron::parse("0 9 * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("0 0 31 2 *").unwrap().next_after(now).is_none());
+    }
+}
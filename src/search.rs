@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::messages::SearchHit;
+
+/// Results returned for a single query.
+const MAX_HITS: usize = 100;
+
+/// Inverted index of words in texts and frame titles of a single board.
+pub struct SearchIndex {
+    words: BTreeMap<String, BTreeSet<usize>>,
+    hits: Vec<SearchHit>,
+}
+
+fn words(text: &str) -> impl Iterator<Item=String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase())
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        SearchIndex {
+            words: BTreeMap::new(),
+            hits: vec![],
+        }
+    }
+
+    pub fn add(&mut self, text: &str, hit: SearchHit) {
+        let document = self.hits.len();
+        self.hits.push(hit);
+        for word in words(text) {
+            self.words.entry(word).or_insert_with(BTreeSet::new).insert(document);
+        }
+    }
+
+    /// Finds content containing all words of the query, the last word may
+    /// be incomplete as the user is probably still typing it.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms: Vec<String> = words(query).collect();
+        let mut matching: Option<BTreeSet<usize>> = None;
+        for (i, term) in terms.iter().enumerate() {
+            let documents: BTreeSet<usize> = if i + 1 == terms.len() {
+                self.words.range(term.clone()..)
+                    .take_while(|(word, _)| word.starts_with(term.as_str()))
+                    .flat_map(|(_, documents)| documents.iter().cloned())
+                    .collect()
+            } else {
+                self.words.get(term).cloned().unwrap_or_default()
+            };
+
+            matching = Some(match matching {
+                Some(t) => t.intersection(&documents).cloned().collect(),
+                None => documents,
+            });
+        }
+
+        matching.unwrap_or_default()
+            .into_iter()
+            .take(MAX_HITS)
+            .map(|x| self.hits[x])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::messages::SearchHit;
+    use super::SearchIndex;
+
+    #[test]
+    fn test_search_matches_all_words() {
+        let mut index = SearchIndex::new();
+        index.add("Q3 roadmap", SearchHit { object_id: None, position: 1 });
+        index.add("Roadmap draft", SearchHit { object_id: Some(7), position: 2 });
+
+        assert_eq!(index.search("roadmap").len(), 2);
+        assert_eq!(index.search("q3 road"), vec![SearchHit { object_id: None, position: 1 }]);
+        assert!(index.search("q4").is_empty());
+        assert!(index.search("").is_empty());
+    }
+}
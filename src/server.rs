@@ -1,30 +1,213 @@
-use std::collections::HashMap;
-use crate::messages::{PALETTE_SIZE, PALETTE_DEFAULT, Message, UserJoin, UserLeave, History, BoardConfiguration, Color, BoardFlags};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::messages::{PALETTE_SIZE, PALETTE_DEFAULT, Message, UserJoin, UserLeave, History, BoardConfiguration, Color, BoardFlags, PollId, CreatePoll, Vote, ClosePoll, PlaceVote, VoteTally, UserId, Stamp, Grid, SetGrid, SetBackground, Connector, CreateFrame, JumpToFrame, Viewport, Draw, Fill, Minimap, RequestRegion, RegionPatch, CreateInvite, Role, SetPrivate, JoinRequest, RespondJoin, NotificationFlags, PresenceState, SetPresence, Typing, ObjectId, SnapshotDelta, Bounds, CreateViewToken, AccessibilityEvent, StepId, LockState, BoardLock, Text, DrawFlags, Position, Reliability, SearchHit, Follow, CreateBookmark, Mention, MissedEvents, SetPalettePreset, Palette, CurveStroke, ClearRegion, Group, Clipboard, PasteObjects, Portal, ResumeToken, UserProfile, SetAccess, AccessList, AccessEntry, TimelineKind, ServerMessage};
 use crate::client::Client;
 use crate::ser::to_bytes;
+use crate::de::from_bytes_prefix;
 use std::num::Wrapping;
 use crate::error::Error;
+use ws::CloseCode;
+use crate::transport::Out;
+use log::{info, warn};
+use crate::poll::Poll;
+use crate::search::SearchIndex;
+use crate::mention;
+use crate::normalize;
+use crate::palettes;
+use crate::templates;
+use crate::schedules::Schedule;
+use crate::workspaces::Workspace;
+use crate::profiles::{Profile, ProfileCache, HttpResolver};
+use crate::avatars::AvatarCache;
+use crate::timeline::Timeline;
+use crate::objects::{ObjectRegistry, BoardObject};
+use crate::canvas::{self, Canvas, CANVAS_WIDTH, CANVAS_HEIGHT, BRUSHES};
+use crate::import::SceneObject;
+use crate::config::Config;
+use crate::entitlements::Plan;
+use crate::invite::{Invite, ViewToken};
+use crate::clock;
+use crate::jobs::Scheduler;
+use crate::latency::{StepLatency, LatencyReport};
+use crate::request;
+use crate::features;
+use std::time::{Instant, Duration};
+use std::sync::Arc;
+use std::rc::Rc;
+use std::cell::Cell;
+
+pub const DEFAULT_VOTE_QUOTA: u8 = 5;
+pub const MINIMAP_INTERVAL: u32 = 512;
+/// Provisional messages resent within this window after a resume are
+/// retries of the client.
+pub const DUPLICATE_WINDOW: Duration = Duration::from_secs(10);
+pub const MAX_RECENT_MUTATIONS: usize = 256;
+/// Admin request timestamps may differ from server time by this many
+/// seconds.
+pub const ADMIN_REPLAY_WINDOW: u64 = 5 * 60;
+/// Number of idempotency keys remembered per user.
+pub const MAX_IDEMPOTENCY_KEYS: usize = 128;
+/// Repeated typing events within this window are not forwarded.
+pub const TYPING_COALESCE: Duration = Duration::from_secs(3);
+/// Full history transmissions started per second on one board. Clients
+/// joining in a burst are admitted one after another at this rate.
+pub const HISTORY_SENDS_PER_SECOND: u32 = 20;
+/// Events kept for each member until their next connection, oldest are
+/// dropped first.
+pub const MAX_MISSED_EVENTS: usize = 32;
+/// Segments of a single curve stroke, bounds rasterization work.
+pub const MAX_CURVE_SEGMENTS: usize = 1024;
+
+/// Objects created by a single paste.
+const MAX_PASTED_OBJECTS: usize = 256;
+/// Entries of the access list of a single board.
+const MAX_ACCESS_ENTRIES: usize = 256;
+/// Clients may resume their session this long after a disconnect.
+pub const RESUME_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// Steps whose history offsets are remembered for resuming clients.
+pub const MAX_RESUME_STEPS: usize = 4096;
+pub const DEFAULT_STICKERS: [&str; 6] = [
+    "/stickers/thumbs-up.svg",
+    "/stickers/thumbs-down.svg",
+    "/stickers/heart.svg",
+    "/stickers/star.svg",
+    "/stickers/question.svg",
+    "/stickers/check.svg",
+];
 
 
 #[derive(Clone)]
 pub struct User {
+    /// Identity the boards, access lists, votes and quotas are keyed on,
+    /// the subject of verified tokens.
     pub username: String,
+    /// Name shown to other members, never used to tell users apart.
+    pub display_name: String,
+    /// Subject of the verified token, `None` for guests and unverified users.
+    pub user_id: Option<String>,
+    pub plan: Plan,
+    pub guest: bool,
+    /// String claims of the verified token other than the standard ones.
+    pub claims: HashMap<String, String>,
+    /// Unix time when the token expires, the client has to present a new
+    /// one with `ReAuth` before then.
+    pub expires_at: Option<u64>,
+    /// Configured admin, see `Config::admins`.
+    pub admin: bool,
 }
 
 /// Main server object holding everything in place.
 pub struct Server {
     boards: HashMap<String, Board>,
+    /// History bytes of all boards per owner, shared with the boards which
+    /// keep it up to date.
+    storage: HashMap<String, Rc<Cell<usize>>>,
+    pub config: Config,
+    /// Signatures of admin requests within the replay window.
+    admin_signatures: HashMap<Vec<u8>, u64>,
+    pub jobs: Scheduler,
+    /// New joins are redirected away while draining, boards with clients
+    /// keep working.
+    pub draining: bool,
+    /// Where redirected clients should connect, the same address when
+    /// not set.
+    pub drain_target: Option<String>,
+    pub schedules: BTreeMap<String, Schedule>,
+    pub workspaces: BTreeMap<String, Workspace>,
+    /// Profiles of users, `None` when no profile service is configured.
+    pub profiles: Option<Arc<ProfileCache>>,
+    /// Proxied avatars of resolved profiles, `None` when not enabled.
+    pub avatars: Option<Arc<AvatarCache>>,
 }
 
 impl Server {
     pub fn new() -> Self {
+        let config = Config::from_env();
+        features::configure(&config.features);
+        let profiles = config.profile_url.clone()
+            .map(|x| Arc::new(ProfileCache::new(Arc::new(HttpResolver::new(x)), Duration::from_secs(config.profile_ttl))));
+        let avatars = match config.avatar_proxy {
+            true => Some(Arc::new(AvatarCache::new(config.avatar_max_bytes, Duration::from_secs(config.avatar_ttl)))),
+            false => None,
+        };
         Server {
             boards: HashMap::new(),
+            storage: HashMap::new(),
+            config,
+            admin_signatures: HashMap::new(),
+            jobs: Scheduler::new(),
+            draining: false,
+            drain_target: None,
+            schedules: BTreeMap::new(),
+            workspaces: BTreeMap::new(),
+            profiles,
+            avatars,
+        }
+    }
+
+    pub fn create(&mut self, name: String, owner: &User) -> &mut Board {
+        let storage = self.storage.entry(owner.username.clone()).or_default().clone();
+        self.boards.entry(name).or_insert_with(|| Board::new(owner, storage))
+    }
+
+    /// Removes the board releasing the storage of its history.
+    fn remove(&mut self, name: &str) -> Option<Board> {
+        let board = self.boards.remove(name)?;
+        board.storage.set(board.storage.get() - board.history.len());
+        Some(board)
+    }
+
+    /// Creates the board with the palette and objects of the template.
+    /// Template placeholders are replaced by the variables, claims of the
+    /// owner and finally the built-in `date`, `board` and `username`
+    /// variables.
+    pub fn create_from_template(&mut self, name: &str, owner: &User, template_id: u64, variables: HashMap<String, String>) -> &mut Board {
+        let template = self.config.template_dir.as_ref().and_then(|x| templates::load(x, template_id));
+
+        let board = self.create(name.to_string(), owner);
+        board.set_palette(palettes::for_template(template_id));
+        if let Some(objects) = template {
+            let mut values: HashMap<String, String> = HashMap::new();
+            values.insert("date".to_string(), templates::date(clock::now()));
+            values.insert("board".to_string(), name.to_string());
+            values.insert("username".to_string(), owner.display_name.clone());
+            values.extend(owner.claims.clone());
+            values.extend(variables);
+            board.import(&templates::instantiate(objects, &values));
         }
+        board
     }
 
-    pub fn create(&mut self, name: String) -> &mut Board {
-        self.boards.entry(name).or_insert(Board::new())
+    /// Role of the user joining the board, `None` when the board is private
+    /// or has an access list and the user neither owns it, is listed in it
+    /// nor is a member of its workspace. Listed roles take precedence over
+    /// the one inherited from the workspace. Admins own every board.
+    pub fn join_role(&self, name: &str, user: &User) -> Option<Role> {
+        let board = self.boards.get(name)?;
+        if board.owner == user.username || user.admin {
+            return Some(Role::Owner);
+        }
+
+        let inherited = board.workspace.as_ref().and_then(|x| self.workspaces.get(x)).and_then(|x| x.role_of(&user.username));
+        match (board.access.get(&user.username).cloned().or(inherited), board.is_restricted()) {
+            (Some(role), _) => Some(role),
+            (None, true) => None,
+            (None, false) => Some(Role::Editor),
+        }
+    }
+
+    /// Boards of a deleted workspace stay as they are, without the access
+    /// it granted.
+    pub fn delete_workspace(&mut self, name: &str) -> bool {
+        if self.workspaces.remove(name).is_none() {
+            return false;
+        }
+
+        for board in self.boards.values_mut().filter(|x| x.workspace.as_ref().map(|x| x == name).unwrap_or(false)) {
+            board.workspace = None;
+        }
+        true
     }
 
     pub fn has_board(&self, name: &str) -> bool {
@@ -34,95 +217,1744 @@ impl Server {
     pub fn find(&mut self, name: &str) -> Option<&mut Board> {
         self.boards.get_mut(name)
     }
+
+    pub fn boards(&self) -> impl Iterator<Item=(&String, &Board)> {
+        self.boards.iter()
+    }
+
+    /// Sends the text as `ServerMessage` to clients of all boards.
+    pub fn announce(&mut self, text: &str) -> Result<(), Error> {
+        let message = to_bytes(&Message::ServerMessage(ServerMessage { message: text }))
+            .map_err(|_| Error::Message("announcement is too long".to_string()))?;
+        for board in self.boards.values_mut() {
+            board.broadcast(&message);
+        }
+        Ok(())
+    }
+
+    /// Deletes the board disconnecting all its clients.
+    pub fn delete(&mut self, name: &str) -> bool {
+        match self.remove(name) {
+            Some(board) => {
+                info!("Deleting board {}", name);
+                board.disconnect_all("board deleted");
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn exceeds_board_quota(&self, user: &User) -> bool {
+        if self.config.quota_exempt.iter().any(|x| *x == user.username) {
+            return false;
+        }
+
+        self.boards.values().filter(|x| x.owner == user.username).count() >= user.plan.entitlements().max_boards
+    }
+
+    /// Storage is accounted to the owner of the board as a sum of history
+    /// sizes of all boards they own.
+    pub fn exceeds_storage_quota(&self, username: &str, additional: usize) -> bool {
+        if self.config.quota_exempt.iter().any(|x| x == username) {
+            return false;
+        }
+
+        let used = self.storage.get(username).map(|x| x.get()).unwrap_or(0);
+        used + additional > self.config.max_storage_bytes
+    }
+
+    /// Remembers the signature of an admin request and returns whether it
+    /// was already used. Signatures older than the replay window are
+    /// forgotten as their timestamps are rejected anyway.
+    pub fn use_admin_signature(&mut self, signature: Vec<u8>, timestamp: u64) -> bool {
+        let now = clock::now();
+        self.admin_signatures.retain(|_, x| *x + ADMIN_REPLAY_WINDOW >= now);
+        self.admin_signatures.insert(signature, timestamp).is_some()
+    }
+
+    /// Creates boards of schedules which are due. Runs missed while the
+    /// server was busy are not repeated, a board which already exists is
+    /// left untouched.
+    pub fn run_schedules(&mut self) {
+        let now = clock::now();
+        let due: Vec<(String, u64)> = self.schedules.iter()
+            .filter_map(|(id, x)| x.next_run.filter(|t| *t <= now).map(|t| (id.clone(), t)))
+            .collect();
+
+        for (id, time) in due {
+            let schedule = self.schedules.get_mut(&id).unwrap();
+            schedule.next_run = schedule.expression.next_after(now);
+            let schedule = schedule.clone();
+
+            let name = schedule.board_name(time);
+            if self.has_board(&name) {
+                warn!("Board {} of schedule {} already exists", name, id);
+                continue;
+            }
+
+            info!("Creating board {} of schedule {}", name, id);
+            let owner = User {
+                username: schedule.owner.clone(),
+                display_name: schedule.owner.clone(),
+                user_id: None,
+                plan: Plan::from_name(Some(&schedule.plan)),
+                guest: false,
+                claims: HashMap::new(),
+                expires_at: None,
+                admin: false,
+            };
+            self.create_from_template(&name, &owner, schedule.template_id, HashMap::new());
+
+            if let Some(url) = self.config.schedule_webhook.clone() {
+                let body = serde_json::json!({ "schedule": id, "board": name, "template_id": schedule.template_id, "owner": schedule.owner }).to_string();
+                self.jobs.submit(&format!("announce board {}", name), move |_| {
+                    ureq::post(&url)
+                        .set("Content-Type", "application/json")
+                        .send_string(&body)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                });
+            }
+        }
+    }
+
+    /// Deletes boards which outlived the retention period.
+    pub fn sweep(&mut self) {
+        let now = clock::now();
+        let retention_days = self.config.retention_days;
+        let expired: Vec<String> = self.boards.iter()
+            .filter(|(_, b)| b.expires_at(retention_days).map(|x| x < now).unwrap_or(false))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in expired {
+            info!("Deleting board {} after retention period", name);
+            if let Some(board) = self.remove(&name) {
+                board.disconnect_all("board expired");
+            }
+        }
+    }
+}
+
+/// Join request waiting for a decision of the board owner.
+struct PendingJoin {
+    out: Out,
+    approved: bool,
+}
+
+/// Connection which left the board and may still be resumed by its token.
+pub struct Departure {
+    pub username: String,
+    pub user_id: UserId,
+    pub role: Role,
+    /// Last step published before the connection left.
+    pub step_id: StepId,
+    left: Instant,
 }
 
 pub struct Board {
+    pub owner: String,
+    pub private: bool,
+    /// Workspace whose members inherit access to the board.
+    pub workspace: Option<String>,
+    /// Roles of users granted access by the owner.
+    access: BTreeMap<String, Role>,
+    max_members: usize,
     clients: Vec<Client>,
     last_client_id: Wrapping<u8>,
-    last_step_id: usize,
+    last_step_id: Wrapping<StepId>,
     history: Vec<u8>,
+    /// Storage used by all boards of the owner.
+    storage: Rc<Cell<usize>>,
+    /// Encoded history messages shared by all joining clients along with
+    /// the last step id they include.
+    history_frames: Option<(StepId, Arc<Vec<Vec<u8>>>)>,
+    /// Rendered snapshot along with the canvas version it shows.
+    snapshot: Option<(u32, Arc<Vec<u8>>)>,
+    /// Earliest time the next full history transmission may start.
+    next_history_slot: Instant,
     pub history_size: u16,
     palette: [u32; PALETTE_SIZE],
     background_color: Color,
+    polls: HashMap<PollId, Poll>,
+    last_poll_id: Wrapping<PollId>,
+    pub vote_quota: u8,
+    used_votes: HashMap<String, u8>,
+    vote_tally: BTreeMap<Position, u16>,
+    stickers: Vec<String>,
+    objects: ObjectRegistry,
+    search: SearchIndex,
+    grid: Option<Grid>,
+    background_image: Option<String>,
+    canvas: Canvas,
+    steps_since_minimap: u32,
+    invite_uses: HashMap<u32, u16>,
+    last_invite_nonce: Wrapping<u32>,
+    join_requests: HashMap<u16, PendingJoin>,
+    last_join_request_id: Wrapping<u16>,
+    notifications: HashMap<UserId, NotificationFlags>,
+    presence: HashMap<UserId, PresenceState>,
+    typing: HashMap<UserId, (Option<ObjectId>, Instant)>,
+    snapshot_versions: HashMap<UserId, u32>,
+    /// Content hashes of recent provisional messages of each connection
+    /// along with the step they became, kept across resumes.
+    recent_mutations: HashMap<UserId, VecDeque<(u64, Option<StepId>, Instant)>>,
+    idempotency_keys: HashMap<String, VecDeque<u32>>,
+    accessibility: HashSet<UserId>,
+    viewports: HashMap<UserId, Bounds>,
+    /// Usernames of everyone who has ever joined the board.
+    known_members: HashSet<String>,
+    /// Encoded events waiting for members who are not connected.
+    missed_events: HashMap<String, VecDeque<Vec<u8>>>,
+    /// Followed user of each following client.
+    following: HashMap<UserId, UserId>,
+    /// Encoded `UserProfile` messages of connected users.
+    profiles: HashMap<UserId, Vec<u8>>,
+    /// Departed connections by their resume token.
+    departures: HashMap<u64, Departure>,
+    /// Length of the history after each of the recent steps.
+    step_offsets: VecDeque<(StepId, usize)>,
+    /// Broadcasts held back from clients which did not get their history
+    /// yet, so nothing newer reaches them before it. Steps are not held
+    /// as the history sent to them includes those.
+    awaiting_history: HashMap<UserId, Vec<Vec<u8>>>,
+    /// Notable events for the activity panel of clients.
+    timeline: Timeline,
+    /// Unix time of the last join or published change.
+    last_activity: u64,
+    lock: Option<(LockState, u16)>,
+    step_latency: StepLatency,
+    /// Messages published since `begin_group`, published together as one
+    /// step by `end_group`.
+    group: Option<Vec<Vec<u8>>>,
+    /// Messages of the board are dumped to the trace log target.
+    pub tracing: bool,
 }
 
 impl Board {
-    fn new() -> Self {
+    fn new(owner: &User, storage: Rc<Cell<usize>>) -> Self {
+        let entitlements = owner.plan.entitlements();
         return Board {
+            owner: owner.username.clone(),
+            private: false,
+            workspace: None,
+            access: BTreeMap::new(),
+            max_members: entitlements.max_members,
             clients: vec![],
             last_client_id: Wrapping(0),
-            last_step_id: 0,
+            last_step_id: Wrapping(0),
             history: vec![],
-            history_size: std::u16::MAX,
+            storage,
+            history_frames: None,
+            snapshot: None,
+            next_history_slot: Instant::now(),
+            history_size: entitlements.history_size,
             palette: PALETTE_DEFAULT,
             background_color: 0,
+            polls: HashMap::new(),
+            last_poll_id: Wrapping(0),
+            vote_quota: DEFAULT_VOTE_QUOTA,
+            used_votes: HashMap::new(),
+            vote_tally: BTreeMap::new(),
+            stickers: DEFAULT_STICKERS.iter().map(|x| x.to_string()).collect(),
+            objects: ObjectRegistry::new(),
+            search: SearchIndex::new(),
+            grid: None,
+            background_image: None,
+            canvas: Canvas::new(0),
+            steps_since_minimap: 0,
+            invite_uses: HashMap::new(),
+            last_invite_nonce: Wrapping(0),
+            join_requests: HashMap::new(),
+            last_join_request_id: Wrapping(0),
+            notifications: HashMap::new(),
+            presence: HashMap::new(),
+            typing: HashMap::new(),
+            snapshot_versions: HashMap::new(),
+            recent_mutations: HashMap::new(),
+            idempotency_keys: HashMap::new(),
+            accessibility: HashSet::new(),
+            viewports: HashMap::new(),
+            known_members: HashSet::new(),
+            missed_events: HashMap::new(),
+            following: HashMap::new(),
+            profiles: HashMap::new(),
+            departures: HashMap::new(),
+            step_offsets: VecDeque::new(),
+            awaiting_history: HashMap::new(),
+            timeline: Timeline::new(),
+            last_activity: clock::now(),
+            lock: None,
+            step_latency: StepLatency::new(),
+            group: None,
+            tracing: false,
         };
     }
 
+    /// Id of the last published step.
+    pub fn last_step_id(&self) -> StepId {
+        self.last_step_id.0
+    }
+
+    /// Whether only some users may join the board.
+    pub fn is_restricted(&self) -> bool {
+        self.private || !self.access.is_empty()
+    }
+
+    pub fn members(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Number of connected clients per reported client version.
+    pub fn client_versions(&self) -> BTreeMap<String, usize> {
+        let mut versions = BTreeMap::new();
+        for x in &self.clients {
+            *versions.entry(x.client_version.clone().unwrap_or_else(|| "unknown".to_string())).or_insert(0) += 1;
+        }
+        versions
+    }
+
+    pub fn canvas_version(&self) -> u32 {
+        self.canvas.version()
+    }
+
+    /// Snapshot is rendered once per canvas version and shared by all
+    /// requests.
+    pub fn render_snapshot(&mut self) -> Arc<Vec<u8>> {
+        let version = self.canvas.version();
+        match &self.snapshot {
+            Some((v, snapshot)) if *v == version => snapshot.clone(),
+            _ => {
+                let snapshot = Arc::new(self.canvas.to_bmp(&self.palette));
+                self.snapshot = Some((version, snapshot.clone()));
+                snapshot
+            }
+        }
+    }
+
+    /// Copies the canvas so the snapshot can be rendered off the event
+    /// loop.
+    pub fn deferred_snapshot(&self) -> impl FnOnce() -> Vec<u8> + Send + 'static {
+        let canvas = self.canvas.clone();
+        let palette = self.palette;
+        move || canvas.to_bmp(&palette)
+    }
+
+    /// Unix time after which the board is deleted unless there is some
+    /// activity, `None` when retention is disabled.
+    pub fn expires_at(&self, retention_days: u64) -> Option<u64> {
+        match retention_days {
+            0 => None,
+            days => Some(self.last_activity + days * 24 * 60 * 60),
+        }
+    }
+
+    /// Locks the board for a maintenance operation expected to take about
+    /// `retry_after` seconds, or unlocks it with `None`.
+    pub fn set_lock(&mut self, lock: Option<(LockState, u16)>) {
+        match lock {
+            Some((state, _)) => self.record_event(TimelineKind::Lock, "", state.name()),
+            None if self.lock.is_some() => self.record_event(TimelineKind::Unlock, "", ""),
+            None => {}
+        }
+        self.lock = lock;
+        self.broadcast(&self.lock_message());
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    pub fn lock_message(&self) -> Vec<u8> {
+        to_bytes(&Message::BoardLock(BoardLock {
+            state: self.lock.map(|(state, _)| state),
+            retry_after: self.lock.map(|(_, x)| x).unwrap_or(0),
+        })).unwrap()
+    }
+
+    pub fn disconnect_all(&self, reason: &str) {
+        for x in &self.clients {
+            let _ = x.out.close(CloseCode::Away, reason.to_string());
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.clients.len() >= self.max_members
+    }
+
     pub fn board_flags(&self) -> BoardFlags {
+        if self.private {
+            return BoardFlags::HISTORY_ENABLED | BoardFlags::PRIVATE;
+        }
         return BoardFlags::HISTORY_ENABLED;
     }
 
     pub fn broadcast(&mut self, message: &Vec<u8>) {
+        self.broadcast_as(message, NotificationFlags::empty())
+    }
+
+    /// Sends the message to clients which did not opt out of notifications
+    /// of this kind. Messages of empty kind are delivered to everyone.
+    pub fn broadcast_as(&mut self, message: &Vec<u8>, kind: NotificationFlags) {
+        self.broadcast_with(message, kind, Reliability::Reliable, false)
+    }
+
+    /// Clients failing to receive a reliable message are disconnected,
+    /// droppable messages are just lost. Messages stored in history are
+    /// skipped for clients still waiting for it.
+    fn broadcast_with(&mut self, message: &Vec<u8>, kind: NotificationFlags, reliability: Reliability, in_history: bool) {
         let initial = std::mem::replace(&mut self.clients, vec![]);
         let mut errs = vec![];
         for x in initial {
-            if let Err(_) = x.out.send(message.clone()) {
+            let user_id = x.board_context.as_ref().unwrap().board_client_id;
+            if !self.notifications.get(&user_id).map(|f| f.contains(kind)).unwrap_or(true) {
+                self.clients.push(x);
+                continue;
+            }
+
+            if let Some(held) = self.awaiting_history.get_mut(&user_id) {
+                if !in_history {
+                    held.push(message.clone());
+                }
+                self.clients.push(x);
+                continue;
+            }
+
+            let result = match reliability {
+                Reliability::Reliable => x.out.send(message.clone()),
+                Reliability::Droppable => x.out.send_droppable(message.clone()).or(Ok(())),
+            };
+            if let Err(_) = result {
                 let leave_message = to_bytes(&Message::UserLeave(UserLeave {
-                    user_id: x.board_context.unwrap().board_client_id,
+                    user_id: x.board_context.as_ref().unwrap().board_client_id,
                 })).unwrap();
                 errs.push(leave_message);
+                self.depart(&x);
             } else {
                 self.clients.push(x);
             }
         }
 
         for err in errs {
-            self.broadcast(&err);
+            self.broadcast_as(&err, NotificationFlags::PRESENCE);
+        }
+    }
+
+    /// Step which the provisional message became when the connection
+    /// already sent it under the same provisional id, that is when the
+    /// client retries it after a resume. As encoding is canonical, equal
+    /// values always hash equally. Deliberate repeats carry a new
+    /// provisional id and are never taken for retries.
+    pub fn retried_step(&mut self, user_id: UserId, provisional_id: u32, message: &[u8]) -> Option<Option<StepId>> {
+        let hash = provisional_hash(provisional_id, message);
+        let recent = self.recent_mutations.get_mut(&user_id)?;
+        let now = Instant::now();
+        while recent.front().map(|(_, _, at)| now - *at > DUPLICATE_WINDOW).unwrap_or(false) {
+            recent.pop_front();
+        }
+
+        recent.iter().find(|(x, _, _)| *x == hash).map(|(_, step_id, _)| *step_id)
+    }
+
+    pub fn remember_step(&mut self, user_id: UserId, provisional_id: u32, message: &[u8], step_id: Option<StepId>) {
+        let recent = self.recent_mutations.entry(user_id).or_insert_with(VecDeque::new);
+        if recent.len() >= MAX_RECENT_MUTATIONS {
+            recent.pop_front();
         }
+        recent.push_back((provisional_hash(provisional_id, message), step_id, Instant::now()));
+    }
+
+    /// Remembers the idempotency key of the user and returns whether it
+    /// was seen before. Keys are kept per user so they survive reconnects.
+    pub fn use_idempotency_key(&mut self, username: &str, key: u32) -> bool {
+        let keys = self.idempotency_keys.entry(username.to_string()).or_insert_with(VecDeque::new);
+        if keys.contains(&key) {
+            return true;
+        }
+
+        if keys.len() >= MAX_IDEMPOTENCY_KEYS {
+            keys.pop_front();
+        }
+        keys.push_back(key);
+        false
     }
 
     pub fn add_to_history(&mut self, message: &Vec<u8>) {
-        self.history.extend(message)
+        self.history.extend(message);
+        self.storage.set(self.storage.get() + message.len());
+    }
+
+    /// Whether the published message is kept in history. Droppable messages
+    /// are superseded by the next one, so they are only broadcast.
+    pub fn stores(&self, reliability: Reliability) -> bool {
+        self.history_size != 0 && reliability == Reliability::Reliable
+    }
+
+    /// Stores the message in history (when enabled) and sends it to all clients.
+    pub fn publish(&mut self, message: &Vec<u8>) {
+        self.publish_as(message, NotificationFlags::empty())
+    }
+
+    pub fn publish_as(&mut self, message: &Vec<u8>, kind: NotificationFlags) {
+        self.publish_with(message, kind, Reliability::Reliable)
+    }
+
+    pub fn publish_with(&mut self, message: &Vec<u8>, kind: NotificationFlags, reliability: Reliability) {
+        if reliability == Reliability::Droppable {
+            self.last_activity = clock::now();
+            return self.broadcast_with(message, kind, reliability, false);
+        }
+
+        if let Some(group) = &mut self.group {
+            group.push(message.clone());
+            return;
+        }
+
+        self.last_activity = clock::now();
+        self.last_step_id += Wrapping(1);
+        if self.history_size != 0 {
+            self.add_to_history(message);
+            if self.step_offsets.len() == MAX_RESUME_STEPS {
+                self.step_offsets.pop_front();
+            }
+            self.step_offsets.push_back((self.last_step_id.0, self.history.len()));
+        }
+
+        self.broadcast_with(message, kind, reliability, self.history_size != 0);
+        if let Some(latency) = request::elapsed() {
+            self.step_latency.record(latency);
+        }
+    }
+
+    pub fn begin_group(&mut self) {
+        self.group = Some(vec![]);
+    }
+
+    /// Publishes messages of the group as a single step. Groups which grew
+    /// too large to be encoded, for example by normalized texts, are
+    /// published message by message instead.
+    pub fn end_group(&mut self) {
+        let messages = match self.group.take() {
+            Some(t) if !t.is_empty() => t,
+            _ => return,
+        };
+
+        let data: Vec<u8> = messages.iter().flatten().cloned().collect();
+        match to_bytes(&Message::Group(Group { data: &data })) {
+            Ok(group) => self.publish(&group),
+            Err(_) => {
+                warn!("Group of {} messages is too large, publishing them separately", messages.len());
+                for message in messages {
+                    self.publish(&message);
+                }
+            }
+        }
+    }
+
+    pub fn step_latency(&self) -> LatencyReport {
+        self.step_latency.report()
     }
 
     pub fn add_client(&mut self, client: &mut Client) -> Result<(), Error> {
+        let user_id = self.last_client_id.0;
+        self.last_client_id += Wrapping(1);
+        /* provisional ids start over with a new connection */
+        self.recent_mutations.remove(&user_id);
+        self.attach(client, user_id)
+    }
+
+    /// Adds the client under the user id, either a new one or the one of
+    /// the resumed connection.
+    pub fn attach(&mut self, client: &mut Client, user_id: UserId) -> Result<(), Error> {
         let user = match &client.authenticated_user {
             Some(t) => t,
             None => return Err(Error::Message("user not authenticated".to_string()))
         };
 
-        client.board_context.as_mut().map(|x| x.board_client_id = self.last_client_id.0);
+        let resume_token = rand::random::<u64>();
+        client.board_context.as_mut().map(|x| {
+            x.board_client_id = user_id;
+            x.resume_token = resume_token;
+        });
 
         let join_message = to_bytes(&Message::UserJoin(UserJoin {
-            username: user.username.as_str(),
-            user_id: self.last_client_id.0,
+            username: user.display_name.as_str(),
+            user_id,
         })).unwrap();
 
-        info!("Client {} has user_id {}", user.username, self.last_client_id.0);
+        info!("Client {} has user_id {}", user.username, user_id);
+        self.last_activity = clock::now();
+        self.known_members.insert(user.display_name.clone());
+        self.timeline.record(self.last_activity, TimelineKind::Join, &user.display_name, String::new());
 
-        self.last_client_id += Wrapping(1);
-        self.broadcast(&join_message);
+        self.broadcast_as(&join_message, NotificationFlags::PRESENCE);
         self.clients.push(client.clone());
+        self.awaiting_history.insert(user_id, vec![]);
 
         /* send board configuration */
-        if let Err(_) = client.out.send(to_bytes(&BoardConfiguration {
+        if let Err(_) = client.out.send(self.configuration_message()) {
+            return Err(Error::Message("cannot send board conf".to_string()));
+        }
+
+        if let Err(_) = client.out.send(to_bytes(&Message::ResumeToken(ResumeToken { token: resume_token })).unwrap()) {
+            return Err(Error::Message("cannot send resume token".to_string()));
+        }
+
+        /* let the client know edits are not possible right now */
+        if self.lock.is_some() && client.out.send(self.lock_message()).is_err() {
+            return Err(Error::Message("cannot send board lock".to_string()));
+        }
+
+        /* send presence roster */
+        for x in &self.clients {
+            let (user, context) = match (&x.authenticated_user, &x.board_context) {
+                (Some(user), Some(context)) => (user, context),
+                _ => continue,
+            };
+
+            let mut roster = vec![to_bytes(&Message::UserJoin(UserJoin {
+                username: user.display_name.as_str(),
+                user_id: context.board_client_id,
+            })).unwrap()];
+
+            if let Some(profile) = self.profiles.get(&context.board_client_id) {
+                roster.push(profile.clone());
+            }
+
+            let state = self.presence(context.board_client_id);
+            if state != PresenceState::Active {
+                roster.push(to_bytes(&Message::SetPresence(SetPresence {
+                    user_id: context.board_client_id,
+                    state,
+                })).unwrap());
+            }
+
+            for message in roster {
+                if let Err(_) = client.out.send(message) {
+                    return Err(Error::Message("cannot send presence roster".to_string()));
+                }
+            }
+        }
+
+        /* send minimap so client has overview before history arrives */
+        if let Err(_) = client.out.send(self.minimap_message()) {
+            return Err(Error::Message("cannot send minimap".to_string()));
+        }
+
+        /* send polls */
+        for (id, poll) in &self.polls {
+            if client.out.send(poll.create_message(*id)).is_err() || client.out.send(poll.results_message(*id)).is_err() {
+                return Err(Error::Message("cannot send polls".to_string()));
+            }
+        }
+
+        /* deliver events which happened while the user was away */
+        if let Some(events) = self.missed_events.remove(&user.display_name) {
+            let data: Vec<u8> = events.into_iter().flatten().collect();
+            for chunk in data.chunks((1 << 16) - 1) {
+                if client.out.send(to_bytes(&Message::MissedEvents(MissedEvents { data: chunk })).unwrap()).is_err() {
+                    return Err(Error::Message("cannot send missed events".to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn configuration_message(&self) -> Vec<u8> {
+        to_bytes(&Message::BoardConfiguration(BoardConfiguration {
             history_size: self.history_size,
             palette: self.palette,
             board_flags: self.board_flags(),
             background: self.background_color,
-        }).unwrap()) {
-            return Err(Error::Message("cannot send board conf".to_string()));
+            vote_quota: self.vote_quota,
+            stickers: self.stickers.iter().map(|x| x.as_str()).collect(),
+            grid: self.grid,
+            background_image: self.background_image.as_ref().map(|x| x.as_str()),
+            brushes: BRUSHES.to_vec(),
+        })).unwrap()
+    }
+
+    pub fn create_poll(&mut self, creator: &str, t: CreatePoll) -> Result<(), Error> {
+        let poll = Poll::new(creator, &t)?;
+        let poll_id = self.last_poll_id.0;
+
+        info!("Client {} created poll {} ({})", creator, poll_id, poll.question);
+
+        self.last_poll_id += Wrapping(1);
+        self.broadcast_as(&poll.create_message(poll_id), NotificationFlags::POLLS);
+        self.broadcast_as(&poll.results_message(poll_id), NotificationFlags::POLLS);
+        self.polls.insert(poll_id, poll);
+        Ok(())
+    }
+
+    pub fn vote(&mut self, username: &str, t: Vote) -> Result<(), Error> {
+        let results = match self.polls.get_mut(&t.poll_id) {
+            Some(poll) => {
+                poll.vote(username, t.option)?;
+                poll.results_message(t.poll_id)
+            }
+            None => return Err(Error::Message("poll not found".to_string()))
+        };
+
+        self.broadcast_as(&results, NotificationFlags::POLLS);
+        Ok(())
+    }
+
+    pub fn close_poll(&mut self, username: &str, t: ClosePoll) -> Result<(), Error> {
+        let results = match self.polls.get_mut(&t.poll_id) {
+            Some(poll) => {
+                poll.close(username)?;
+                poll.results_message(t.poll_id)
+            }
+            None => return Err(Error::Message("poll not found".to_string()))
+        };
+
+        info!("Client {} closed poll {}", username, t.poll_id);
+        self.broadcast_as(&results, NotificationFlags::POLLS);
+        Ok(())
+    }
+
+    pub fn place_vote(&mut self, username: &str, user_id: UserId, t: PlaceVote) -> Result<(), Error> {
+        self.check_vote_quota(username, 1)?;
+        *self.used_votes.entry(username.to_string()).or_insert(0) += 1;
+        let votes = self.vote_tally.entry(t.position).or_insert(0);
+        *votes = votes.saturating_add(1);
+
+        self.publish(&to_bytes(&Message::PlaceVote(PlaceVote {
+            position: t.position,
+            user_id,
+        })).unwrap());
+        Ok(())
+    }
+
+    /// Votes placed on the board per position, ordered by position.
+    pub fn vote_tally(&self) -> Vec<VoteTally> {
+        self.vote_tally.iter().map(|(position, votes)| VoteTally { position: *position, votes: *votes }).collect()
+    }
+
+    fn check_vote_quota(&self, username: &str, votes: u8) -> Result<(), Error> {
+        let used = self.used_votes.get(username).cloned().unwrap_or(0);
+        if used.saturating_add(votes) > self.vote_quota {
+            return Err(Error::Message("vote quota exhausted".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn stamp(&mut self, t: Stamp) -> Result<ObjectId, Error> {
+        self.check_sticker(&t)?;
+
+        let object_id = self.objects.insert(BoardObject::Stamp {
+            position: t.position,
+            sticker_id: t.sticker_id,
+        });
+
+        self.publish(&to_bytes(&Message::Stamp(Stamp {
+            object_id,
+            position: t.position,
+            sticker_id: t.sticker_id,
+        })).unwrap());
+        self.announce(object_id);
+        Ok(object_id)
+    }
+
+    fn check_sticker(&self, t: &Stamp) -> Result<(), Error> {
+        if t.sticker_id as usize >= self.stickers.len() {
+            return Err(Error::Message("sticker not in catalog".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn set_grid(&mut self, username: &str, t: SetGrid) -> Result<(), Error> {
+        self.check_grid(username, &t)?;
+        self.grid = t.grid;
+        self.broadcast(&to_bytes(&Message::SetGrid(t)).unwrap());
+        Ok(())
+    }
+
+    fn check_grid(&self, username: &str, t: &SetGrid) -> Result<(), Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can change the grid".to_string()));
+        }
+
+        if let Some(Grid { cell_size: 0, .. }) = t.grid {
+            return Err(Error::Message("grid cell size must be positive".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.snapshot = None;
+    }
+
+    /// Colors of the canvas change along with the palette, clients receive
+    /// the new palette in a fresh board configuration.
+    pub fn set_palette_preset(&mut self, username: &str, t: SetPalettePreset) -> Result<(), Error> {
+        let palette = self.check_palette_preset(username, &t)?;
+        self.set_palette(palette);
+        self.broadcast(&self.configuration_message());
+        Ok(())
+    }
+
+    fn check_palette_preset(&self, username: &str, t: &SetPalettePreset) -> Result<Palette, Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can change the palette".to_string()));
+        }
+
+        match palettes::preset(t.preset) {
+            Some(t) => Ok(t),
+            None => Err(Error::Message("palette preset does not exist".to_string())),
+        }
+    }
+
+    /// Background image is not a part of the history so it is
+    /// unaffected by undo and erasing.
+    pub fn set_background(&mut self, username: &str, t: SetBackground) -> Result<(), Error> {
+        self.check_background(username)?;
+        self.background_image = t.url.map(|x| x.to_string());
+        self.broadcast(&to_bytes(&Message::SetBackground(t)).unwrap());
+        Ok(())
+    }
+
+    fn check_background(&self, username: &str) -> Result<(), Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can change the background".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Connector endpoints reference objects instead of positions so they
+    /// follow the objects when those are moved.
+    pub fn connect(&mut self, t: Connector) -> Result<ObjectId, Error> {
+        self.check_connector(&t)?;
+
+        let object_id = self.objects.insert(BoardObject::Connector {
+            from_object: t.from_object,
+            to_object: t.to_object,
+            style: t.style,
+        });
+
+        self.publish(&to_bytes(&Message::Connector(Connector { object_id, ..t })).unwrap());
+        self.announce(object_id);
+        Ok(object_id)
+    }
+
+    fn check_connector(&self, t: &Connector) -> Result<(), Error> {
+        if !self.objects.contains(t.from_object) || !self.objects.contains(t.to_object) {
+            return Err(Error::Message("connected object does not exist".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Places objects of an imported scene onto the board. The scene is
+    /// moved to the top left corner and scaled down to fit the canvas,
+    /// colors are mapped to the nearest palette entry.
+    pub fn import(&mut self, objects: &[SceneObject]) {
+        self.record_event(TimelineKind::Import, "", &format!("{} objects", objects.len()));
+        let extent: Vec<(f64, f64)> = objects.iter().flat_map(|x| x.extent()).collect();
+        let min_x = extent.iter().map(|x| x.0).fold(f64::INFINITY, f64::min);
+        let min_y = extent.iter().map(|x| x.1).fold(f64::INFINITY, f64::min);
+        let max_x = extent.iter().map(|x| x.0).fold(f64::NEG_INFINITY, f64::max);
+        let max_y = extent.iter().map(|x| x.1).fold(f64::NEG_INFINITY, f64::max);
+        let scale = ((CANVAS_WIDTH - 1) as f64 / (max_x - min_x).max(1.0))
+            .min((CANVAS_HEIGHT - 1) as f64 / (max_y - min_y).max(1.0))
+            .min(1.0);
+        let pixel = |(x, y): (f64, f64)| -> (i64, i64) {
+            (((x - min_x) * scale).round() as i64, ((y - min_y) * scale).round() as i64)
+        };
+        let position = |(x, y): (i64, i64)| -> Position {
+            let x = x.max(0).min(CANVAS_WIDTH as i64 - 1) as u32;
+            let y = y.max(0).min(CANVAS_HEIGHT as i64 - 1) as u32;
+            y * CANVAS_WIDTH + x
+        };
+
+        for object in objects {
+            match object {
+                SceneObject::Stroke { points, color } => {
+                    let color = self.nearest_color(*color);
+                    let points: Vec<(i64, i64)> = points.iter().map(|x| pixel(*x)).collect();
+                    let mut previous = match points.first() {
+                        Some(t) => *t,
+                        None => continue,
+                    };
+                    for current in points {
+                        let steps = (current.0 - previous.0).abs().max((current.1 - previous.1).abs()).max(1);
+                        for i in 0..=steps {
+                            let x = previous.0 + (current.0 - previous.0) * i / steps;
+                            let y = previous.1 + (current.1 - previous.1) * i / steps;
+                            let d = Draw { position: position((x, y)), color, flags: DrawFlags(0) };
+                            self.draw(&d);
+                            self.publish(&to_bytes(&Message::Draw(d)).unwrap());
+                        }
+                        previous = current;
+                    }
+                }
+                SceneObject::Shape { x, y, width, height, title } => {
+                    self.create_frame(CreateFrame {
+                        object_id: 0,
+                        bounds: Bounds {
+                            start: position(pixel((*x, *y))),
+                            end: position(pixel((x + width, y + height))),
+                        },
+                        title: title.as_str(),
+                    });
+                }
+                SceneObject::Text { x, y, text, color } => {
+                    let text = normalize::text(text);
+                    let t = Text {
+                        center: position(pixel((*x, *y))),
+                        text: text.as_str(),
+                        text_color: self.nearest_color(*color),
+                    };
+                    self.index_text(&t);
+                    self.publish(&to_bytes(&Message::Text(t)).unwrap());
+                }
+            }
         }
+    }
+
+    fn nearest_color(&self, rgb: (u8, u8, u8)) -> Color {
+        canvas::nearest(&self.palette, rgb)
+    }
+
+    pub fn create_frame(&mut self, t: CreateFrame) -> ObjectId {
+        let title = normalize::text(t.title);
+        let object_id = self.objects.insert(BoardObject::Frame {
+            bounds: t.bounds,
+            title: title.clone(),
+        });
+        self.search.add(&title, SearchHit { object_id: Some(object_id), position: t.bounds.start });
+
+        self.publish(&to_bytes(&Message::CreateFrame(CreateFrame { object_id, title: &title, ..t })).unwrap());
+        self.announce(object_id);
+        object_id
+    }
+
+    pub fn index_text(&mut self, t: &Text) {
+        self.search.add(t.text, SearchHit { object_id: None, position: t.center });
+    }
+
+    /// Notifies members mentioned in the text of the author. Members who are
+    /// not connected receive the mention when they join next time.
+    pub fn notify_mentions(&mut self, author: UserId, t: &Text) {
+        let message = to_bytes(&Message::Mention(Mention {
+            user_id: author,
+            position: t.center,
+            text: t.text,
+        })).unwrap();
+
+        for username in mention::parse(t.text) {
+            let mentioned: Vec<&Client> = self.clients.iter()
+                .filter(|x| x.authenticated_user.as_ref().map(|x| x.display_name == username).unwrap_or(false))
+                .collect();
+            if mentioned.is_empty() {
+                if self.known_members.contains(username) {
+                    info!("User {} mentioned {} at {} while away", author, username, t.center);
+                    self.queue_missed_event(username, message.clone());
+                }
+                continue;
+            }
+
+            info!("User {} mentioned {} at {}", author, username, t.center);
+            for client in mentioned {
+                let _ = client.out.send(message.clone());
+            }
+        }
+    }
+
+    fn queue_missed_event(&mut self, username: &str, message: Vec<u8>) {
+        let events = self.missed_events.entry(username.to_string()).or_insert_with(VecDeque::new);
+        if events.len() == MAX_MISSED_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(message);
+    }
+
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        self.search.search(query)
+    }
+
+    /// Remembers the viewport of the user and relays it to its followers.
+    pub fn set_viewport(&mut self, user_id: UserId, bounds: Bounds) {
+        self.viewports.insert(user_id, bounds);
+
+        let message = to_bytes(&Message::Viewport(Viewport { user_id, bounds })).unwrap();
+        for client in &self.clients {
+            let client_id = client.board_context.as_ref().unwrap().board_client_id;
+            if self.following.get(&client_id) == Some(&user_id) {
+                /* followers catch up with the next update */
+                let _ = client.out.send_droppable(message.clone());
+            }
+        }
+    }
+
+    /// Returns viewport message moving the requesting client onto the
+    /// viewport of another user.
+    pub fn user_viewport(&self, user_id: UserId) -> Result<Vec<u8>, Error> {
+        match self.viewports.get(&user_id) {
+            Some(bounds) => Ok(to_bytes(&Message::Viewport(Viewport {
+                user_id,
+                bounds: *bounds,
+            })).unwrap()),
+            None => Err(Error::Message("viewport of the user is unknown".to_string())),
+        }
+    }
+
+    /// Starts or stops following. Returns the current viewport of the
+    /// followed user so the follower moves right away.
+    pub fn follow(&mut self, user_id: UserId, t: Follow) -> Result<Option<Vec<u8>>, Error> {
+        match t.user_id {
+            Some(followed) if followed == user_id => Err(Error::Message("cannot follow self".to_string())),
+            Some(followed) => {
+                self.following.insert(user_id, followed);
+                Ok(self.user_viewport(followed).ok())
+            }
+            None => {
+                self.following.remove(&user_id);
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn create_bookmark(&mut self, t: CreateBookmark) -> ObjectId {
+        let label = normalize::text(t.label);
+        let object_id = self.objects.insert(BoardObject::Bookmark {
+            position: t.position,
+            zoom: t.zoom,
+            label: label.clone(),
+        });
+        self.search.add(&label, SearchHit { object_id: Some(object_id), position: t.position });
+
+        self.publish(&to_bytes(&Message::CreateBookmark(CreateBookmark { object_id, label: &label, ..t })).unwrap());
+        self.announce(object_id);
+        object_id
+    }
+
+    /// The target board is checked only when the portal is entered as it
+    /// may be created or deleted at any time.
+    pub fn create_portal(&mut self, t: Portal) -> ObjectId {
+        let object_id = self.objects.insert(BoardObject::Portal {
+            position: t.position,
+            target_board: t.target_board.to_string(),
+        });
+
+        self.publish(&to_bytes(&Message::Portal(Portal { object_id, ..t })).unwrap());
+        self.announce(object_id);
+        object_id
+    }
+
+    pub fn portal_target(&self, object_id: ObjectId) -> Option<String> {
+        match self.objects.get(object_id) {
+            Some(BoardObject::Portal { target_board, .. }) => Some(target_board.clone()),
+            _ => None,
+        }
+    }
+
+    /// Removes the connection which closed or moved to another board.
+    pub fn remove_client(&mut self, connection_id: u32) {
+        let user_id = match self.clients.iter().position(|x| x.connection_id == connection_id) {
+            Some(i) => {
+                let client = self.clients.remove(i);
+                self.depart(&client);
+                client.board_context.unwrap().board_client_id
+            }
+            None => return,
+        };
+
+        self.viewports.remove(&user_id);
+        self.following.retain(|follower, followed| *follower != user_id && *followed != user_id);
+        self.broadcast_as(&to_bytes(&Message::UserLeave(UserLeave { user_id })).unwrap(), NotificationFlags::PRESENCE);
+    }
+
+    fn depart(&mut self, client: &Client) {
+        let (user, context) = match (&client.authenticated_user, &client.board_context) {
+            (Some(user), Some(context)) => (user, context),
+            _ => return,
+        };
+
+        self.profiles.remove(&context.board_client_id);
+        self.timeline.record(clock::now(), TimelineKind::Leave, &user.display_name, String::new());
+        self.departures.retain(|_, x| x.left.elapsed() < RESUME_WINDOW);
+        /* without the history there is nothing to resume from */
+        if self.awaiting_history.remove(&context.board_client_id).is_some() {
+            return;
+        }
+        self.departures.insert(context.resume_token, Departure {
+            username: user.username.clone(),
+            user_id: context.board_client_id,
+            role: context.role,
+            step_id: self.last_step_id.0,
+            left: Instant::now(),
+        });
+    }
+
+    /// Shows the profile of the connected user to everyone in the board.
+    pub fn set_profile(&mut self, user_id: UserId, profile: &Profile) {
+        let message = match to_bytes(&Message::UserProfile(UserProfile {
+            user_id,
+            display_name: &profile.display_name,
+            avatar_url: &profile.avatar_url,
+        })) {
+            Ok(t) => t,
+            Err(_) => return warn!("Profile of user_id {} is too large", user_id),
+        };
+
+        self.profiles.insert(user_id, message.clone());
+        self.broadcast_as(&message, NotificationFlags::PRESENCE);
+    }
+
+    /// Takes the departure of the token when the user may resume it. A
+    /// connection whose close was not noticed yet is removed first.
+    pub fn take_departure(&mut self, token: u64, username: &str) -> Result<Departure, Error> {
+        let stale = self.clients.iter()
+            .find(|x| x.board_context.as_ref().map(|x| x.resume_token) == Some(token))
+            .map(|x| x.connection_id);
+        if let Some(connection_id) = stale {
+            self.remove_client(connection_id);
+        }
+
+        let departure = match self.departures.remove(&token) {
+            Some(t) if t.left.elapsed() < RESUME_WINDOW && t.username == username => t,
+            _ => return Err(Error::Message("nothing to resume".to_string())),
+        };
+
+        let taken = self.clients.iter().any(|x| x.board_context.as_ref().map(|x| x.board_client_id) == Some(departure.user_id));
+        if taken {
+            return Err(Error::Message("user id was taken".to_string()));
+        }
+        Ok(departure)
+    }
+
+    /// History published after the step, `None` when it is no longer
+    /// known and the whole history has to be sent.
+    pub fn history_since(&self, step_id: StepId) -> Option<Vec<Vec<u8>>> {
+        let offset = match self.step_offsets.iter().find(|(id, _)| *id == step_id) {
+            Some((_, offset)) => *offset,
+            None if step_id == self.last_step_id.0 => self.history.len(),
+            None => return None,
+        };
+
+        Some(self.history[offset..].chunks((1 << 16) - 1)
+            .map(|x| to_bytes(&Message::History(History { data: x })).unwrap())
+            .collect())
+    }
+
+    /// Stops holding back broadcasts from the client once its history is
+    /// on the way and returns those held so far, to be sent after it.
+    pub fn history_sent(&mut self, user_id: UserId) -> Vec<Vec<u8>> {
+        self.awaiting_history.remove(&user_id).unwrap_or_default()
+    }
+
+    /// Encodes the objects as the messages which created them.
+    pub fn copy_objects(&self, ids: &[ObjectId]) -> Result<Vec<u8>, Error> {
+        let mut blob = vec![];
+        for &object_id in ids {
+            let message = match self.objects.get(object_id) {
+                Some(BoardObject::Stamp { position, sticker_id }) => Message::Stamp(Stamp { object_id, position: *position, sticker_id: *sticker_id }),
+                Some(BoardObject::Connector { from_object, to_object, style }) => Message::Connector(Connector { object_id, from_object: *from_object, to_object: *to_object, style: *style }),
+                Some(BoardObject::Frame { bounds, title }) => Message::CreateFrame(CreateFrame { object_id, bounds: *bounds, title }),
+                Some(BoardObject::Bookmark { position, zoom, label }) => Message::CreateBookmark(CreateBookmark { object_id, position: *position, zoom: *zoom, label }),
+                Some(BoardObject::Portal { position, target_board }) => Message::Portal(Portal { object_id, position: *position, target_board }),
+                None => return Err(Error::Message("copied object does not exist".to_string())),
+            };
+            blob.extend(to_bytes(&message).unwrap());
+        }
+
+        to_bytes(&Message::Clipboard(Clipboard { blob: &blob }))
+            .map_err(|_| Error::Message("too many objects to copy".to_string()))
+    }
+
+    /// Creates the clipboard objects with new ids as one step. The blob
+    /// comes from the client so it is validated completely before anything
+    /// is created. Connectors are kept only when both of their ends were
+    /// copied along with them.
+    pub fn paste_objects(&mut self, t: &PasteObjects) -> Result<(), Error> {
+        let objects = self.pasted_objects(t)?;
+
+        /* a paste inside a group becomes part of that group */
+        let grouped = self.group.is_none();
+        if grouped {
+            self.begin_group();
+        }
+
+        let mut ids = HashMap::new();
+        for object in &objects {
+            /* objects were validated above, nothing fails halfway through the group */
+            let (copied, pasted) = match object {
+                Message::Stamp(s) => (s.object_id, self.stamp(Stamp { ..*s }).ok()),
+                Message::CreateFrame(f) => (f.object_id, Some(self.create_frame(CreateFrame { ..*f }))),
+                Message::CreateBookmark(b) => (b.object_id, Some(self.create_bookmark(CreateBookmark { ..*b }))),
+                Message::Portal(p) => (p.object_id, Some(self.create_portal(Portal { ..*p }))),
+                _ => continue,
+            };
+            if let Some(pasted) = pasted {
+                ids.insert(copied, pasted);
+            }
+        }
+        for object in &objects {
+            if let Message::Connector(c) = object {
+                if let (Some(&from_object), Some(&to_object)) = (ids.get(&c.from_object), ids.get(&c.to_object)) {
+                    if let Ok(pasted) = self.connect(Connector { from_object, to_object, ..*c }) {
+                        ids.insert(c.object_id, pasted);
+                    }
+                }
+            }
+        }
+
+        if grouped {
+            self.end_group();
+        }
+        info!("Pasted {} objects", ids.len());
+        Ok(())
+    }
+
+    /// Decodes and validates objects of the clipboard, moved by the offset.
+    fn pasted_objects<'a>(&self, t: &PasteObjects<'a>) -> Result<Vec<Message<'a>>, Error> {
+        let mut messages = vec![];
+
+        let mut offset = 0;
+        while offset < t.blob.len() {
+            let (message, size) = from_bytes_prefix::<Message>(&t.blob[offset..])?;
+            messages.push(message);
+            offset += size;
+        }
+        if messages.len() > MAX_PASTED_OBJECTS {
+            return Err(Error::Message("too many objects to paste".to_string()));
+        }
+
+        let shift = |position: Position| canvas::translate(position, t.offset_x, t.offset_y)
+            .ok_or_else(|| Error::Message("pasted object outside of the canvas".to_string()));
+        let mut objects = vec![];
+        for message in messages {
+            objects.push(match message {
+                Message::Stamp(s) if (s.sticker_id as usize) < self.stickers.len() => Message::Stamp(Stamp { position: shift(s.position)?, ..s }),
+                Message::Connector(c) => Message::Connector(c),
+                Message::CreateFrame(f) => Message::CreateFrame(CreateFrame { bounds: Bounds { start: shift(f.bounds.start)?, end: shift(f.bounds.end)? }, ..f }),
+                Message::CreateBookmark(b) => Message::CreateBookmark(CreateBookmark { position: shift(b.position)?, ..b }),
+                Message::Portal(p) => Message::Portal(Portal { position: shift(p.position)?, ..p }),
+                _ => return Err(Error::Message("clipboard holds an invalid object".to_string())),
+            });
+        }
+
+        Ok(objects)
+    }
+
+    /// Rejects the group when any of its mutations would be rejected, so
+    /// none of it is applied. Owner only changes are checked against the
+    /// acting username, everything else against the username.
+    pub fn check_group(&self, username: &str, acting_username: &str, messages: &[Message]) -> Result<(), Error> {
+        let votes = messages.iter().filter(|x| matches!(x, Message::PlaceVote(_))).count();
+        self.check_vote_quota(username, votes.min(u8::MAX as usize) as u8)?;
+
+        for message in messages {
+            match message {
+                Message::CreatePoll(t) => Poll::new(username, t).map(|_| ())?,
+                Message::ClosePoll(t) => match self.polls.get(&t.poll_id) {
+                    Some(poll) => poll.check_close(username)?,
+                    None => return Err(Error::Message("poll not found".to_string())),
+                },
+                Message::Stamp(t) => self.check_sticker(t)?,
+                Message::Connector(t) => self.check_connector(t)?,
+                Message::SetGrid(t) => self.check_grid(acting_username, t)?,
+                Message::SetBackground(_) => self.check_background(acting_username)?,
+                Message::SetPalettePreset(t) => self.check_palette_preset(acting_username, t).map(|_| ())?,
+                Message::CurveStroke(t) => check_curve(t)?,
+                Message::PasteObjects(t) => self.pasted_objects(t).map(|_| ())?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Bookmarks in the order they were created.
+    pub fn bookmarks(&self) -> Vec<(ObjectId, Position, u16, &str)> {
+        self.objects.ids().into_iter().filter_map(|x| match self.objects.get(x) {
+            Some(BoardObject::Bookmark { position, zoom, label }) => Some((x, *position, *zoom, label.as_str())),
+            _ => None,
+        }).collect()
+    }
+
+    /// Returns viewport message moving the requesting client onto the frame.
+    pub fn jump_to_frame(&self, user_id: UserId, t: JumpToFrame) -> Result<Vec<u8>, Error> {
+        match self.objects.get(t.object_id) {
+            Some(BoardObject::Frame { bounds, .. }) => Ok(to_bytes(&Message::Viewport(Viewport {
+                user_id,
+                bounds: *bounds,
+            })).unwrap()),
+            _ => Err(Error::Message("frame does not exist".to_string()))
+        }
+    }
+
+    pub fn draw(&mut self, t: &Draw) {
+        self.canvas.draw(t, &self.palette);
+        self.tick_minimap();
+    }
+
+    pub fn fill(&mut self, t: &Fill) {
+        self.canvas.fill(t);
+        self.tick_minimap();
+    }
+
+    /// Curves are kept in history in their compact form, only the canvas
+    /// holds the rasterized pixels.
+    pub fn curve(&mut self, t: &CurveStroke) -> Result<(), Error> {
+        check_curve(t)?;
+        self.canvas.curve(t);
+        self.tick_minimap();
+        Ok(())
+    }
+
+    pub fn clear_region(&mut self, t: &ClearRegion) {
+        self.canvas.fill(&Fill { start: t.bounds.start, end: t.bounds.end, color: self.background_color });
+        self.tick_minimap();
+    }
+
+    /// Pixel steps before the clear no longer show anything so they are
+    /// dropped from history, undo cannot go back past a clear.
+    pub fn clear_all(&mut self) {
+        self.canvas.fill(&Fill { start: 0, end: CANVAS_WIDTH * CANVAS_HEIGHT - 1, color: self.background_color });
+        self.tick_minimap();
+
+        let mut compacted = Vec::with_capacity(self.history.len());
+        let mut offset = 0;
+        while offset < self.history.len() {
+            match from_bytes_prefix::<Message>(&self.history[offset..]) {
+                Ok((Message::Draw(_), size)) | Ok((Message::Fill(_), size)) | Ok((Message::CurveStroke(_), size)) |
+                Ok((Message::ClearRegion(_), size)) | Ok((Message::ClearAll(_), size)) => offset += size,
+                Ok((_, size)) => {
+                    compacted.extend_from_slice(&self.history[offset..offset + size]);
+                    offset += size;
+                }
+                /* history which cannot be decoded is kept as it is */
+                Err(_) => {
+                    compacted.extend_from_slice(&self.history[offset..]);
+                    break;
+                }
+            }
+        }
+        info!("Clearing board dropped {} bytes of history", self.history.len() - compacted.len());
+        self.storage.set(self.storage.get() - (self.history.len() - compacted.len()));
+        self.history = compacted;
+        self.step_offsets.clear();
+    }
+
+    fn tick_minimap(&mut self) {
+        self.steps_since_minimap += 1;
+        if self.steps_since_minimap >= MINIMAP_INTERVAL {
+            self.steps_since_minimap = 0;
+            self.broadcast(&self.minimap_message());
+        }
+    }
+
+    fn minimap_message(&self) -> Vec<u8> {
+        let (width, height, data) = self.canvas.minimap();
+        to_bytes(&Message::Minimap(Minimap {
+            width,
+            height,
+            data: data.as_slice(),
+            bookmarks: self.bookmarks().into_iter().map(|(_, position, _, _)| position).collect(),
+        })).unwrap()
+    }
+
+    /// Encoded published messages one after another.
+    pub fn raw_history(&self) -> &[u8] {
+        &self.history
+    }
 
-        /* send history */
-        for x in self.history.chunks((1 << 16) - 1) {
-            let history = to_bytes(&History { data: x }).unwrap();
-            if let Err(_) = client.out.send(history) {
-                return Err(Error::Message("cannot send history".to_string()));
+    /// History is sent separately from the rest of the board state so the
+    /// client can request its viewport region first. The history is split
+    /// into chunks fitting into a single message and encoded once per step
+    /// for all clients.
+    pub fn history_frames(&mut self) -> Arc<Vec<Vec<u8>>> {
+        let step_id = self.last_step_id.0;
+        match &self.history_frames {
+            Some((id, frames)) if *id == step_id => frames.clone(),
+            _ => {
+                let frames: Arc<Vec<Vec<u8>>> = Arc::new(self.history.chunks((1 << 16) - 1)
+                    .map(|x| to_bytes(&Message::History(History { data: x })).unwrap())
+                    .collect());
+                self.history_frames = Some((step_id, frames.clone()));
+                frames
             }
         }
+    }
+
+    /// Reserves a slot for full history transmission and returns how long
+    /// the client has to wait for it.
+    pub fn reserve_history_slot(&mut self) -> Duration {
+        let now = Instant::now();
+        let slot = self.next_history_slot.max(now);
+        self.next_history_slot = slot + Duration::from_secs(1) / HISTORY_SENDS_PER_SECOND;
+        slot - now
+    }
+
+    pub fn region_patches(&self, t: RequestRegion) -> Vec<Vec<u8>> {
+        self.patches(&t.bounds)
+    }
+
+    fn patches(&self, bounds: &Bounds) -> Vec<Vec<u8>> {
+        self.canvas.region(bounds).iter().map(|(bounds, data)| {
+            to_bytes(&Message::RegionPatch(RegionPatch {
+                bounds: *bounds,
+                data: data.as_slice(),
+            })).unwrap()
+        }).collect()
+    }
+
+    pub fn create_invite(&mut self, username: &str, board_name: &str, t: CreateInvite, secret: &[u8]) -> Result<String, Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can create invites".to_string()));
+        }
 
+        if t.role == Role::Owner {
+            return Err(Error::Message("cannot invite as owner".to_string()));
+        }
+
+        let nonce = self.last_invite_nonce.0;
+        self.last_invite_nonce += Wrapping(1);
+        self.invite_uses.insert(nonce, 0);
+
+        info!("Client {} created invite {} to board {}", username, nonce, board_name);
+        Ok(Invite {
+            board_name: board_name.to_string(),
+            role: t.role,
+            expires_at: clock::now() + t.expires_in as u64,
+            max_uses: t.max_uses,
+            nonce,
+        }.sign(secret))
+    }
+
+    pub fn create_view_token(&self, username: &str, board_name: &str, t: CreateViewToken, secret: &[u8]) -> Result<String, Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can create view tokens".to_string()));
+        }
+
+        info!("Client {} created view token to board {}", username, board_name);
+        Ok(ViewToken {
+            board_name: board_name.to_string(),
+            expires_at: clock::now() + t.expires_in as u64,
+        }.sign(secret))
+    }
+
+    pub fn use_invite(&mut self, invite: &Invite) -> Result<(), Error> {
+        match self.invite_uses.get_mut(&invite.nonce) {
+            Some(uses) if *uses < invite.max_uses => {
+                *uses += 1;
+                Ok(())
+            }
+            Some(_) => Err(Error::Message("invite used up".to_string())),
+            None => Err(Error::Message("invite not issued by this board".to_string())),
+        }
+    }
+
+    pub fn set_private(&mut self, username: &str, t: SetPrivate) -> Result<(), Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can change board privacy".to_string()));
+        }
+
+        self.private = t.private;
+        self.broadcast(&to_bytes(&Message::SetPrivate(t)).unwrap());
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Connected users keep their role until they join again.
+    pub fn set_access(&mut self, username: &str, t: SetAccess) -> Result<Vec<u8>, Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can change board access".to_string()));
+        }
+
+        match t.role {
+            Some(Role::Owner) => return Err(Error::Message("owner role cannot be granted".to_string())),
+            Some(_) if !self.access.contains_key(t.username) && self.access.len() >= MAX_ACCESS_ENTRIES => {
+                return Err(Error::Message("access list is full".to_string()));
+            }
+            Some(role) => self.access.insert(t.username.to_string(), role),
+            None => self.access.remove(t.username),
+        };
+        self.access_list(username)
+    }
+
+    /// Records a notable event, an empty username stands for the server.
+    pub fn record_event(&mut self, kind: TimelineKind, username: &str, detail: &str) {
+        self.timeline.record(clock::now(), kind, username, detail.to_string());
+    }
+
+    pub fn timeline_message(&self) -> Result<Vec<u8>, Error> {
+        self.timeline.message()
+    }
+
+    pub fn access_list(&self, username: &str) -> Result<Vec<u8>, Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can see board access".to_string()));
+        }
+
+        to_bytes(&Message::AccessList(AccessList {
+            entries: self.access.iter().map(|(username, role)| AccessEntry { username, role: *role }).collect(),
+        })).map_err(|_| Error::Message("cannot encode access list".to_string()))
+    }
+
+    /// Forwards the join request to all connected owners. Returns id of the
+    /// request which is later used to pick up the decision.
+    pub fn request_join(&mut self, username: &str, out: &Out) -> Result<u16, Error> {
+        let request_id = self.last_join_request_id.0;
+        let request = to_bytes(&Message::JoinRequest(JoinRequest {
+            request_id,
+            username,
+        })).unwrap();
+
+        let mut forwarded = false;
+        for client in &self.clients {
+            let is_owner = client.authenticated_user.as_ref().map(|x| x.username == self.owner).unwrap_or(false);
+            if is_owner && client.out.send(request.clone()).is_ok() {
+                forwarded = true;
+            }
+        }
+
+        if !forwarded {
+            return Err(Error::Message("no owner online".to_string()));
+        }
+
+        self.last_join_request_id += Wrapping(1);
+        self.join_requests.insert(request_id, PendingJoin { out: out.clone(), approved: false });
+        Ok(request_id)
+    }
+
+    /// Records the decision and returns sender of the waiting connection
+    /// which should be notified.
+    pub fn respond_join(&mut self, username: &str, t: RespondJoin) -> Result<Out, Error> {
+        if self.owner != username {
+            return Err(Error::Message("only owner can respond to join requests".to_string()));
+        }
+
+        match self.join_requests.get_mut(&t.request_id) {
+            Some(request) => {
+                request.approved = t.approve;
+                Ok(request.out.clone())
+            }
+            None => Err(Error::Message("join request not found".to_string()))
+        }
+    }
+
+    /// Removes the join request returning whether it was approved.
+    pub fn take_join_request(&mut self, request_id: u16) -> Option<bool> {
+        self.join_requests.remove(&request_id).map(|x| x.approved)
+    }
+
+    pub fn set_notifications(&mut self, user_id: UserId, flags: NotificationFlags) {
+        self.notifications.insert(user_id, flags);
+    }
+
+    /// Returns descriptions of all existing objects for the newly
+    /// subscribed client, or nothing when unsubscribing.
+    pub fn set_accessibility(&mut self, user_id: UserId, enabled: bool) -> Vec<Vec<u8>> {
+        if !enabled {
+            self.accessibility.remove(&user_id);
+            return vec![];
+        }
+
+        self.accessibility.insert(user_id);
+        self.objects.ids().into_iter().filter_map(|x| self.accessibility_event(x)).collect()
+    }
+
+    fn accessibility_event(&self, object_id: ObjectId) -> Option<Vec<u8>> {
+        let description = self.objects.describe(object_id, &self.stickers)?;
+        Some(to_bytes(&Message::AccessibilityEvent(AccessibilityEvent {
+            object_id,
+            description: description.as_str(),
+        })).unwrap())
+    }
+
+    /// Sends description of the new object to subscribed clients.
+    fn announce(&mut self, object_id: ObjectId) {
+        if self.accessibility.is_empty() {
+            return;
+        }
+
+        let message = match self.accessibility_event(object_id) {
+            Some(t) => t,
+            None => return,
+        };
+
+        for x in &self.clients {
+            let user_id = x.board_context.as_ref().unwrap().board_client_id;
+            if self.accessibility.contains(&user_id) {
+                let _ = x.out.send(message.clone());
+            }
+        }
+    }
+
+    pub fn presence(&self, user_id: UserId) -> PresenceState {
+        self.presence.get(&user_id).cloned().unwrap_or(PresenceState::Active)
+    }
+
+    pub fn set_presence(&mut self, user_id: UserId, state: PresenceState) {
+        if self.presence(user_id) == state {
+            return;
+        }
+
+        self.presence.insert(user_id, state);
+        self.broadcast_as(&to_bytes(&Message::SetPresence(SetPresence {
+            user_id,
+            state,
+        })).unwrap(), NotificationFlags::PRESENCE);
+    }
+
+    /// Typing events are ephemeral and never stored in history.
+    pub fn typing(&mut self, user_id: UserId, t: Typing) {
+        if t.active {
+            if let Some((context, since)) = self.typing.get(&user_id) {
+                if *context == t.context && since.elapsed() < TYPING_COALESCE {
+                    return;
+                }
+            }
+            self.typing.insert(user_id, (t.context, Instant::now()));
+        } else if self.typing.remove(&user_id).is_none() {
+            return;
+        }
+
+        self.broadcast_as(&to_bytes(&Message::Typing(Typing { user_id, ..t })).unwrap(), NotificationFlags::TYPING);
+    }
+
+    /// Returns changes of the canvas since the snapshot last sent to the
+    /// client, or since the empty canvas for the first snapshot.
+    pub fn snapshot_delta(&mut self, user_id: UserId) -> Vec<Vec<u8>> {
+        let base_version = self.snapshot_versions.get(&user_id).cloned().unwrap_or(0);
+        let version = self.canvas.version();
+        let tiles = self.canvas.changed_tiles(base_version);
+
+        let mut messages = vec![to_bytes(&Message::SnapshotDelta(SnapshotDelta {
+            base_version,
+            version,
+            tiles: tiles.len() as u16,
+        })).unwrap()];
+        for tile in &tiles {
+            messages.extend(self.patches(tile));
+        }
+
+        self.snapshot_versions.insert(user_id, version);
+        messages
+    }
+}
+
+fn check_curve(t: &CurveStroke) -> Result<(), Error> {
+    let mut positions = std::iter::once(t.start).chain(t.segments.iter().flat_map(|x| vec![x.control1, x.control2, x.end]));
+    if t.segments.is_empty() || t.segments.len() > MAX_CURVE_SEGMENTS {
+        return Err(Error::Message("invalid number of curve segments".to_string()));
+    }
+    if !positions.all(canvas::contains) {
+        return Err(Error::Message("curve outside of the canvas".to_string()));
+    }
+    Ok(())
+}
+
+fn provisional_hash(provisional_id: u32, message: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    provisional_id.hash(&mut hasher);
+    message.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Sends encoded history messages prepared by `Board::history_frames`.
+pub fn send_history(frames: &[Vec<u8>], out: &Out) -> Result<(), Error> {
+    for x in frames {
+        if let Err(_) = out.send_backfill(x.clone()) {
+            return Err(Error::Message("cannot send history".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use crate::entitlements::Plan;
+    use crate::messages::{Message, Stamp, Connector, Draw, DrawFlags, PlaceVote, VoteTally, CursorMove, NotificationFlags, Reliability};
+    use crate::ser::to_bytes;
+    use super::{Board, Server, User};
+
+    fn owner() -> User {
+        User {
+            username: "alice".to_string(),
+            display_name: "alice".to_string(),
+            user_id: None,
+            plan: Plan::Free,
+            guest: false,
+            claims: HashMap::new(),
+            expires_at: None,
+            admin: false,
+        }
+    }
+
+    #[test]
+    fn test_storage_quota() {
+        let mut server = Server::new();
+        server.config.quota_exempt = vec![];
+        server.config.max_storage_bytes = 100;
+        let draw = to_bytes(&Message::Draw(Draw { position: 0, color: 1, flags: DrawFlags(0) })).unwrap();
+        let cursor = to_bytes(&Message::CursorMove(CursorMove { position: 0, user_id: 0 })).unwrap();
+        for name in &["first", "second"] {
+            let board = server.create(name.to_string(), &owner());
+            board.publish(&draw);
+            board.publish_with(&cursor, NotificationFlags::CURSORS, Reliability::Droppable);
+        }
+        assert!(!server.exceeds_storage_quota("alice", 100 - 2 * draw.len()));
+        assert!(server.exceeds_storage_quota("alice", 100 - 2 * draw.len() + 1));
+
+        server.find("first").unwrap().clear_all();
+        assert!(!server.exceeds_storage_quota("alice", 100 - draw.len()));
+        assert!(server.exceeds_storage_quota("alice", 100 - draw.len() + 1));
+
+        server.delete("second");
+        assert!(!server.exceeds_storage_quota("alice", 100));
+        assert!(server.exceeds_storage_quota("alice", 101));
+    }
+
+    #[test]
+    fn test_retried_step() {
+        let mut board = Board::new(&owner(), Default::default());
+        let stroke = b"stroke".to_vec();
+        assert_eq!(board.retried_step(0, 1, &stroke), None);
+        board.remember_step(0, 1, &stroke, Some(7));
+
+        assert_eq!(board.retried_step(0, 1, &stroke), Some(Some(7)));
+        /* the same stroke drawn again on purpose */
+        assert_eq!(board.retried_step(0, 2, &stroke), None);
+        assert_eq!(board.retried_step(1, 1, &stroke), None);
+    }
+
+    #[test]
+    fn test_vote_tally() {
+        let mut board = Board::new(&owner(), Default::default());
+        board.place_vote("alice", 0, PlaceVote { position: 20, user_id: 0 }).unwrap();
+        board.place_vote("alice", 0, PlaceVote { position: 10, user_id: 0 }).unwrap();
+        board.place_vote("bob", 1, PlaceVote { position: 20, user_id: 1 }).unwrap();
+
+        assert_eq!(board.vote_tally(), vec![
+            VoteTally { position: 10, votes: 1 },
+            VoteTally { position: 20, votes: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_check_group() {
+        let mut board = Board::new(&owner(), Default::default());
+        let stamp = Stamp { object_id: 0, position: 10, sticker_id: 0 };
+        let existing = board.stamp(Stamp { ..stamp }).unwrap();
+
+        let valid = [
+            Message::Stamp(Stamp { ..stamp }),
+            Message::Connector(Connector { object_id: 0, from_object: existing, to_object: existing, style: 0 }),
+            Message::Draw(Draw { position: 20, color: 1, flags: DrawFlags(0) }),
+        ];
+        assert!(board.check_group("alice", "alice", &valid).is_ok());
+
+        let invalid = [
+            Message::Stamp(Stamp { ..stamp }),
+            Message::Connector(Connector { object_id: 0, from_object: existing, to_object: existing + 1, style: 0 }),
+            Message::Draw(Draw { position: 20, color: 1, flags: DrawFlags(0) }),
+        ];
+        assert!(board.check_group("alice", "alice", &invalid).is_err());
+    }
+}
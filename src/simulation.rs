@@ -0,0 +1,286 @@
+//! Deterministic simulation of virtual clients talking to the server in
+//! process. Clients are driven through their handlers over memory
+//! transports and their timeouts fire on a virtual clock which only moves
+//! when the simulation advances it, so a seeded scenario always plays out
+//! the same way no matter how long thousands of operations take.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use ws::{CloseCode, Handler};
+use ws::util::Token;
+use crate::client::{Client, SERVER};
+use crate::de::{from_bytes, from_bytes_prefix};
+use crate::messages::{Message, Position};
+use crate::ser::to_bytes;
+use crate::transport::MemoryTransport;
+
+/// Connection of a virtual client and what it has seen so far.
+pub struct VirtualClient {
+    pub client: Client,
+    pub transport: Arc<MemoryTransport>,
+    pub open: bool,
+    /// Positions of draws in the order they arrived, both from history
+    /// and live.
+    pub draws: Vec<Position>,
+    pub resume_token: Option<u64>,
+    /// Frames already looked at.
+    seen: usize,
+    /// History not decoded yet as a message may span two frames.
+    history: Vec<u8>,
+}
+
+pub struct Simulation {
+    /// Virtual time in milliseconds.
+    pub now: u64,
+    pub clients: Vec<VirtualClient>,
+    pub rng: StdRng,
+    /// Due time, order of scheduling, client index and timeout token.
+    timers: BinaryHeap<Reverse<(u64, u64, usize, usize)>>,
+    scheduled: u64,
+}
+
+impl Simulation {
+    /// Tokens of the users are taken as their usernames.
+    pub fn new(seed: u64) -> Self {
+        SERVER.with(|x| x.borrow_mut().config.insecure_dev_auth = true);
+        Simulation {
+            now: 0,
+            clients: vec![],
+            rng: StdRng::seed_from_u64(seed),
+            timers: BinaryHeap::new(),
+            scheduled: 0,
+        }
+    }
+
+    /// Opens a new connection and returns its index.
+    pub fn connect(&mut self) -> usize {
+        let transport = Arc::new(MemoryTransport::default());
+        self.clients.push(VirtualClient {
+            client: Client::new(transport.clone()),
+            transport,
+            open: true,
+            draws: vec![],
+            resume_token: None,
+            seen: 0,
+            history: vec![],
+        });
+        self.clients.len() - 1
+    }
+
+    pub fn send(&mut self, index: usize, message: &Message) {
+        let frame = to_bytes(message).unwrap();
+        if self.clients[index].open {
+            self.clients[index].client.on_message(ws::Message::Binary(frame)).unwrap();
+        }
+        self.settle(index);
+    }
+
+    /// Closes the connection as if the network went away.
+    pub fn disconnect(&mut self, index: usize) {
+        if self.clients[index].open {
+            self.clients[index].open = false;
+            self.clients[index].client.on_close(CloseCode::Away, "");
+        }
+        self.settle_all();
+    }
+
+    /// Moves the virtual clock forward firing every timeout due in order.
+    pub fn advance(&mut self, ms: u64) {
+        let until = self.now + ms;
+        while let Some(Reverse((due, _, index, token))) = self.timers.peek().cloned() {
+            if due > until {
+                break;
+            }
+            self.timers.pop();
+            self.now = due;
+            if self.clients[index].open {
+                self.clients[index].client.on_timeout(Token(token)).unwrap();
+            }
+            self.settle(index);
+        }
+        self.now = until;
+    }
+
+    pub fn random(&mut self, bound: u32) -> u32 {
+        self.rng.gen_range(0, bound)
+    }
+
+    /// Schedules timeouts the client asked for and closes connections the
+    /// server closed. Handlers reach other clients through the board, so
+    /// all of them are looked at.
+    fn settle(&mut self, index: usize) {
+        let timeouts: Vec<(u64, Token)> = self.clients[index].transport.timeouts.lock().unwrap().drain(..).collect();
+        for (ms, token) in timeouts {
+            self.scheduled += 1;
+            self.timers.push(Reverse((self.now + ms, self.scheduled, index, token.0)));
+        }
+        self.settle_all();
+    }
+
+    fn settle_all(&mut self) {
+        for index in 0..self.clients.len() {
+            let closed = self.clients[index].transport.closed.lock().unwrap().clone();
+            if let (true, Some((code, reason))) = (self.clients[index].open, closed) {
+                self.clients[index].open = false;
+                self.clients[index].client.on_close(code, &reason);
+            }
+            self.clients[index].observe();
+        }
+    }
+}
+
+impl VirtualClient {
+    fn observe(&mut self) {
+        let sent = self.transport.sent.lock().unwrap();
+        for frame in &sent[self.seen..] {
+            match from_bytes::<Message>(frame) {
+                Ok(Message::Draw(t)) => self.draws.push(t.position),
+                Ok(Message::ResumeToken(t)) => self.resume_token = Some(t.token),
+                Ok(Message::History(t)) => self.history.extend_from_slice(t.data),
+                _ => {}
+            }
+
+            let mut offset = 0;
+            while let Ok((message, size)) = from_bytes_prefix::<Message>(&self.history[offset..]) {
+                if let Message::Draw(t) = message {
+                    self.draws.push(t.position);
+                }
+                offset += size;
+            }
+            self.history.drain(..offset);
+        }
+        self.seen = sent.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::messages::{Message, Auth, Create, Join, Draw, DrawFlags, Resume, Position};
+    use crate::entitlements::Plan;
+    use crate::server::MAX_RESUME_STEPS;
+    use super::Simulation;
+
+    /// Long enough for the history backfill to be sent and flushed.
+    const BACKFILL_MS: u64 = 5000;
+
+    fn draw(sim: &mut Simulation, index: usize, position: Position) {
+        sim.send(index, &Message::Draw(Draw { position, color: 1, flags: DrawFlags(0) }));
+    }
+
+    fn join(sim: &mut Simulation, username: &str, board: &str) -> usize {
+        let index = sim.connect();
+        sim.send(index, &Message::Auth(Auth { jwt_token: username }));
+        sim.send(index, &Message::Join(Join { name: board }));
+        index
+    }
+
+    fn create(sim: &mut Simulation, username: &str, board: &str) -> usize {
+        let index = sim.connect();
+        sim.send(index, &Message::Auth(Auth { jwt_token: username }));
+        sim.send(index, &Message::Create(Create { template_id: 0, name: board }));
+        index
+    }
+
+    fn resume(sim: &mut Simulation, index: usize, username: &str, board: &str) -> usize {
+        let token = sim.clients[index].resume_token.unwrap();
+        let resumed = sim.connect();
+        sim.send(resumed, &Message::Auth(Auth { jwt_token: username }));
+        sim.send(resumed, &Message::Resume(Resume { board_name: board, token }));
+        resumed
+    }
+
+    #[test]
+    fn test_clients_see_every_draw_in_order() {
+        let mut sim = Simulation::new(1);
+        let mut clients = vec![create(&mut sim, "owner", "ordering")];
+        let mut published = vec![];
+
+        for position in 0..5000 {
+            /* boards of the free plan are full with ten members */
+            if clients.len() < Plan::Free.entitlements().max_members && sim.random(100) == 0 {
+                let username = format!("user{}", clients.len());
+                clients.push(join(&mut sim, &username, "ordering"));
+            }
+
+            let index = clients[sim.random(clients.len() as u32) as usize];
+            draw(&mut sim, index, position);
+            published.push(position);
+
+            let elapsed = sim.random(40) as u64;
+            sim.advance(elapsed);
+        }
+        sim.advance(BACKFILL_MS);
+
+        assert_eq!(clients.len(), Plan::Free.entitlements().max_members);
+        for index in clients {
+            assert!(sim.clients[index].open);
+            assert_eq!(sim.clients[index].draws, published);
+        }
+    }
+
+    #[test]
+    fn test_resume_fills_the_gap() {
+        let mut sim = Simulation::new(2);
+        let owner = create(&mut sim, "owner", "gaps");
+        let mut watcher = join(&mut sim, "watcher", "gaps");
+        sim.advance(BACKFILL_MS);
+
+        let mut published = vec![];
+        let mut seen = vec![];
+        for _ in 0..50 {
+            for _ in 0..sim.random(100) {
+                draw(&mut sim, owner, published.len() as Position);
+                published.push(published.len() as Position);
+            }
+
+            sim.disconnect(watcher);
+            seen.extend_from_slice(&sim.clients[watcher].draws);
+            for _ in 0..sim.random(100) {
+                draw(&mut sim, owner, published.len() as Position);
+                published.push(published.len() as Position);
+            }
+
+            let elapsed = sim.random(1000) as u64;
+            sim.advance(elapsed);
+            watcher = resume(&mut sim, watcher, "watcher", "gaps");
+            /* draws published right after the resume keep their order */
+            draw(&mut sim, owner, published.len() as Position);
+            published.push(published.len() as Position);
+            sim.advance(BACKFILL_MS);
+        }
+        seen.extend_from_slice(&sim.clients[watcher].draws);
+
+        assert!(published.len() > 1000);
+        assert_eq!(seen, published);
+    }
+
+    #[test]
+    fn test_resume_past_trimmed_steps_sends_whole_history() {
+        let mut sim = Simulation::new(3);
+        let owner = create(&mut sim, "owner", "trimming");
+        let watcher = join(&mut sim, "watcher", "trimming");
+        sim.advance(BACKFILL_MS);
+
+        let mut published: Vec<Position> = (0..100).collect();
+        for position in &published {
+            draw(&mut sim, owner, *position);
+        }
+        sim.disconnect(watcher);
+
+        /* steps the watcher would resume from are no longer remembered */
+        for position in 100..100 + MAX_RESUME_STEPS as Position {
+            draw(&mut sim, owner, position);
+            published.push(position);
+        }
+        let watcher = resume(&mut sim, watcher, "watcher", "trimming");
+        assert!(sim.clients[watcher].draws.is_empty());
+        draw(&mut sim, owner, published.len() as Position);
+        published.push(published.len() as Position);
+        sim.advance(BACKFILL_MS);
+
+        assert_eq!(sim.clients[watcher].draws, published);
+    }
+}
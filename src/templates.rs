@@ -0,0 +1,78 @@
+//! Board templates are Excalidraw or tldraw scenes stored in the template
+//! directory as `<template_id>.json`. Texts and frame titles of a template
+//! may contain placeholders like `{{date}}` or `{{team}}` which are
+//! replaced when a board is created from it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use log::warn;
+use crate::import::{self, SceneObject};
+use crate::clock;
+
+/// Objects of the template, `None` when there is no such template.
+pub fn load(dir: &Path, template_id: u64) -> Option<Vec<SceneObject>> {
+    let path = dir.join(format!("{}.json", template_id));
+    let json = fs::read_to_string(&path).ok()?;
+    match import::parse(&json) {
+        Ok(t) => Some(t),
+        Err(e) => {
+            warn!("Cannot load template {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Replaces placeholders in texts and frame titles with the variables.
+pub fn instantiate(objects: Vec<SceneObject>, variables: &HashMap<String, String>) -> Vec<SceneObject> {
+    objects.into_iter().map(|x| match x {
+        SceneObject::Text { x, y, text, color } => SceneObject::Text { x, y, text: substitute(&text, variables), color },
+        SceneObject::Shape { x, y, width, height, title } => SceneObject::Shape { x, y, width, height, title: substitute(&title, variables) },
+        stroke => stroke,
+    }).collect()
+}
+
+/// Unknown placeholders are left in place so the author of the template
+/// notices them.
+pub fn substitute(text: &str, variables: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(t) => start + t,
+            None => break,
+        };
+
+        result.push_str(&rest[..start]);
+        match variables.get(rest[start + 2..end].trim()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Formats unix time as `YYYY-MM-DD` in UTC.
+pub fn date(unix: u64) -> String {
+    let (year, month, day) = clock::civil(unix);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use super::{substitute, date};
+
+    #[test]
+    fn test_substitute() {
+        let mut variables = HashMap::new();
+        variables.insert("team".to_string(), "Platform".to_string());
+        variables.insert("date".to_string(), date(1_700_000_000));
+
+        assert_eq!(substitute("{{team}} retro {{ date }}", &variables), "Platform retro 2023-11-14");
+        assert_eq!(substitute("{{unknown}} {{team", &variables), "{{unknown}} {{team");
+        assert_eq!(date(0), "1970-01-01");
+    }
+}
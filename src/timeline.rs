@@ -0,0 +1,89 @@
+//! Condensed activity of a board for the activity panel of clients. Unlike
+//! history it tells who did what and when, for notable events only.
+
+use std::collections::VecDeque;
+use crate::messages::{Message, Timeline as TimelineMessage, TimelineEvent, TimelineKind};
+use crate::ser::to_bytes;
+use crate::error::Error;
+
+/// Older events are forgotten.
+const MAX_EVENTS: usize = 256;
+/// Repeated events of a user are merged when this many seconds apart at
+/// most, so reconnects do not flood the timeline.
+const CONDENSE_WINDOW: u64 = 10 * 60;
+
+struct Event {
+    time: u64,
+    kind: TimelineKind,
+    /// Empty for events caused by the server or an admin.
+    username: String,
+    detail: String,
+    count: u16,
+}
+
+pub struct Timeline {
+    events: VecDeque<Event>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Timeline { events: VecDeque::new() }
+    }
+
+    pub fn record(&mut self, time: u64, kind: TimelineKind, username: &str, detail: String) {
+        if let Some(last) = self.events.back_mut() {
+            if last.kind == kind && last.username == username && last.detail == detail && time.saturating_sub(last.time) <= CONDENSE_WINDOW {
+                last.time = time;
+                last.count = last.count.saturating_add(1);
+                return;
+            }
+        }
+
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(Event { time, kind, username: username.to_string(), detail, count: 1 });
+    }
+
+    /// Events from the oldest one.
+    pub fn message(&self) -> Result<Vec<u8>, Error> {
+        to_bytes(&Message::Timeline(TimelineMessage {
+            events: self.events.iter().map(|x| TimelineEvent {
+                time: x.time,
+                kind: x.kind,
+                username: &x.username,
+                detail: &x.detail,
+                count: x.count,
+            }).collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::messages::{Message, TimelineKind};
+    use crate::de::from_bytes;
+    use super::{Timeline, CONDENSE_WINDOW};
+
+    #[test]
+    fn test_condensing() {
+        let mut timeline = Timeline::new();
+        timeline.record(100, TimelineKind::Join, "alice", String::new());
+        timeline.record(160, TimelineKind::Join, "alice", String::new());
+        timeline.record(170, TimelineKind::Join, "bob", String::new());
+        timeline.record(170 + CONDENSE_WINDOW + 1, TimelineKind::Join, "bob", String::new());
+        timeline.record(2000, TimelineKind::Paste, "alice", "3 objects".to_string());
+
+        let message = timeline.message().unwrap();
+        let events = match from_bytes::<Message>(&message).unwrap() {
+            Message::Timeline(t) => t.events.into_iter().map(|x| (x.time, x.username.to_string(), x.count)).collect::<Vec<_>>(),
+            _ => panic!("expected timeline"),
+        };
+        assert_eq!(events, vec![
+            (160, "alice".to_string(), 2),
+            (170, "bob".to_string(), 1),
+            (170 + CONDENSE_WINDOW + 1, "bob".to_string(), 1),
+            (2000, "alice".to_string(), 1),
+        ]);
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+use ws::{Sender, CloseCode, Result};
+use ws::util::Token;
+
+/// Connection of a client regardless of the protocol it arrived over.
+/// Handlers only talk to clients through this trait.
+pub trait Transport: Send + Sync {
+    fn send(&self, frame: Vec<u8>) -> Result<()>;
+
+    /// Sends a frame which may be lost, such as a cursor move. Transports
+    /// with unreliable channels use them, others send it as usual and the
+    /// caller ignores failures.
+    fn send_droppable(&self, frame: Vec<u8>) -> Result<()> {
+        self.send(frame)
+    }
+
+    /// Sends a frame of history backfill which may wait for live traffic.
+    fn send_backfill(&self, frame: Vec<u8>) -> Result<()> {
+        self.send(frame)
+    }
+
+    fn close(&self, code: CloseCode, reason: String) -> Result<()>;
+
+    /// Schedules `Handler::on_timeout` with the token after `ms`.
+    fn timeout(&self, ms: u64, token: Token) -> Result<()>;
+
+    /// Describes the remote end for logs.
+    fn peer(&self) -> String;
+}
+
+/// Transports are shared between the client, its board and jobs.
+pub type Out = Arc<dyn Transport>;
+
+impl Transport for Sender {
+    fn send(&self, frame: Vec<u8>) -> Result<()> {
+        Sender::send(self, frame)
+    }
+
+    fn close(&self, code: CloseCode, reason: String) -> Result<()> {
+        self.close_with_reason(code, reason)
+    }
+
+    fn timeout(&self, ms: u64, token: Token) -> Result<()> {
+        Sender::timeout(self, ms, token)
+    }
+
+    fn peer(&self) -> String {
+        format!("websocket #{}", self.connection_id())
+    }
+}
+
+/// Keeps everything sent in memory so handlers can be driven in-process.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryTransport {
+    pub sent: std::sync::Mutex<Vec<Vec<u8>>>,
+    pub closed: std::sync::Mutex<Option<(CloseCode, String)>>,
+    pub timeouts: std::sync::Mutex<Vec<(u64, Token)>>,
+}
+
+#[cfg(test)]
+impl Transport for MemoryTransport {
+    fn send(&self, frame: Vec<u8>) -> Result<()> {
+        self.sent.lock().unwrap().push(frame);
+        Ok(())
+    }
+
+    fn close(&self, code: CloseCode, reason: String) -> Result<()> {
+        *self.closed.lock().unwrap() = Some((code, reason));
+        Ok(())
+    }
+
+    fn timeout(&self, ms: u64, token: Token) -> Result<()> {
+        self.timeouts.lock().unwrap().push((ms, token));
+        Ok(())
+    }
+
+    fn peer(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use ws::Handler;
+    use crate::client::{Client, SERVER, IDLE_CHECK};
+    use crate::messages::{Message, TimeSync, Join, Auth, Create, Draw, DrawFlags, CreateViewToken, JoinView, Vote, CursorMove};
+    use crate::ser::to_bytes;
+    use crate::de::from_bytes;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn test_time_sync_over_memory_transport() {
+        let transport = Arc::new(MemoryTransport::default());
+        let mut client = Client::new(transport.clone());
+        let request = to_bytes(&Message::TimeSync(TimeSync { client_send_time: 42, server_receive_time: 0, server_send_time: 0 })).unwrap();
+        client.on_message(ws::Message::Binary(request)).unwrap();
+
+        let sent = transport.sent.lock().unwrap();
+        match from_bytes::<Message>(sent[0].as_slice()).unwrap() {
+            Message::TimeSync(t) => assert_eq!(t.client_send_time, 42),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_join_before_auth_closes_memory_transport() {
+        let transport = Arc::new(MemoryTransport::default());
+        let mut client = Client::new(transport.clone());
+        let request = to_bytes(&Message::Join(Join { name: "board" })).unwrap();
+        client.on_message(ws::Message::Binary(request)).unwrap();
+
+        assert!(transport.closed.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_viewer_cannot_vote() {
+        SERVER.with(|x| x.borrow_mut().config.insecure_dev_auth = true);
+        let owner = Arc::new(MemoryTransport::default());
+        let mut client = Client::new(owner.clone());
+        for message in &[Message::Auth(Auth { jwt_token: "alice" }), Message::Create(Create { template_id: 0, name: "viewed" }), Message::CreateViewToken(CreateViewToken { expires_in: 600 })] {
+            client.on_message(ws::Message::Binary(to_bytes(message).unwrap())).unwrap();
+        }
+        let token = owner.sent.lock().unwrap().iter().find_map(|x| match from_bytes::<Message>(x) {
+            Ok(Message::ViewToken(t)) => Some(t.token.to_string()),
+            _ => None,
+        }).unwrap();
+
+        for message in &[Message::Vote(Vote { poll_id: 0, option: 0 }), Message::CursorMove(CursorMove { position: 0, user_id: 0 })] {
+            let transport = Arc::new(MemoryTransport::default());
+            let mut viewer = Client::new(transport.clone());
+            viewer.on_message(ws::Message::Binary(to_bytes(&Message::JoinView(JoinView { token: &token })).unwrap())).unwrap();
+            assert!(transport.closed.lock().unwrap().is_none());
+
+            viewer.on_message(ws::Message::Binary(to_bytes(message).unwrap())).unwrap();
+            let closed = transport.closed.lock().unwrap().clone();
+            assert!(closed.map(|(_, reason)| reason.starts_with("read-only access")).unwrap_or(false));
+        }
+    }
+
+    #[test]
+    fn test_message_to_deleted_board_closes_memory_transport() {
+        SERVER.with(|x| x.borrow_mut().config.insecure_dev_auth = true);
+        let transport = Arc::new(MemoryTransport::default());
+        let mut client = Client::new(transport.clone());
+        for message in &[Message::Auth(Auth { jwt_token: "alice" }), Message::Create(Create { template_id: 0, name: "deleted" })] {
+            client.on_message(ws::Message::Binary(to_bytes(message).unwrap())).unwrap();
+        }
+        assert!(transport.closed.lock().unwrap().is_none());
+
+        SERVER.with(|x| x.borrow_mut().delete("deleted"));
+        *transport.closed.lock().unwrap() = None;
+        let draw = to_bytes(&Message::Draw(Draw { position: 0, color: 1, flags: DrawFlags(0) })).unwrap();
+        client.on_message(ws::Message::Binary(draw)).unwrap();
+
+        let closed = transport.closed.lock().unwrap().clone();
+        assert!(closed.map(|(_, reason)| reason.starts_with("board deleted")).unwrap_or(false));
+
+        /* timers keep firing until the close handshake completes */
+        client.last_activity = Instant::now() - Duration::from_secs(3600);
+        assert!(client.on_timeout(IDLE_CHECK).is_ok());
+    }
+}
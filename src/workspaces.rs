@@ -0,0 +1,55 @@
+//! Workspaces group boards of a team. Members of a workspace may join all
+//! of its boards, private ones included, with the default role of the
+//! workspace unless they own the board.
+
+use std::collections::BTreeSet;
+use serde::Serialize;
+use crate::messages::Role;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Workspace {
+    pub members: BTreeSet<String>,
+    pub default_role: Role,
+}
+
+impl Workspace {
+    /// Members are a comma separated list of usernames. Owners are given
+    /// by the boards so the default role cannot be `owner`.
+    pub fn new(members: &str, default_role: &str) -> Result<Workspace, String> {
+        let default_role = match Role::from_name(default_role) {
+            Some(Role::Owner) => return Err("default role cannot be owner".to_string()),
+            Some(t) => t,
+            None => return Err(format!("unknown role {}", default_role)),
+        };
+
+        Ok(Workspace {
+            members: members.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(|x| x.to_string()).collect(),
+            default_role,
+        })
+    }
+
+    /// Role inherited by the user in boards of the workspace.
+    pub fn role_of(&self, username: &str) -> Option<Role> {
+        match self.members.contains(username) {
+            true => Some(self.default_role),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::messages::Role;
+    use super::Workspace;
+
+    #[test]
+    fn test_role_of() {
+        let workspace = Workspace::new("alice, bob,,", "viewer").unwrap();
+        assert_eq!(workspace.members.len(), 2);
+        assert_eq!(workspace.role_of("bob"), Some(Role::Viewer));
+        assert_eq!(workspace.role_of("mallory"), None);
+
+        assert!(Workspace::new("alice", "owner").is_err());
+        assert!(Workspace::new("alice", "admin").is_err());
+    }
+}